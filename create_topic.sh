@@ -4,7 +4,7 @@
 echo "Waiting for Kafka to start..."
 sleep 10
 
-# Create topic
+# Create topics
 kafka-topics --create \
   --bootstrap-server kafka:29092 \
   --replication-factor 1 \
@@ -12,4 +12,39 @@ kafka-topics --create \
   --topic weather-data \
   --if-not-exists
 
+kafka-topics --create \
+  --bootstrap-server kafka:29092 \
+  --replication-factor 1 \
+  --partitions 1 \
+  --topic weather-data-dlq \
+  --if-not-exists
+
+kafka-topics --create \
+  --bootstrap-server kafka:29092 \
+  --replication-factor 1 \
+  --partitions 1 \
+  --topic weather-observations \
+  --if-not-exists
+
+kafka-topics --create \
+  --bootstrap-server kafka:29092 \
+  --replication-factor 1 \
+  --partitions 1 \
+  --topic weather-observations-dlq \
+  --if-not-exists
+
+kafka-topics --create \
+  --bootstrap-server kafka:29092 \
+  --replication-factor 1 \
+  --partitions 1 \
+  --topic air-quality-forecast \
+  --if-not-exists
+
+kafka-topics --create \
+  --bootstrap-server kafka:29092 \
+  --replication-factor 1 \
+  --partitions 1 \
+  --topic air-quality-forecast-dlq \
+  --if-not-exists
+
 echo "Topic creation script completed."
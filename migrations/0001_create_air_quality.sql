@@ -13,4 +13,4 @@ CREATE TABLE IF NOT EXISTS air_quality (
     aerosol_optical_depth DOUBLE PRECISION NOT NULL,
     us_aqi BIGINT NOT NULL,
     insert_time TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-)
\ No newline at end of file
+);
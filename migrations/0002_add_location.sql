@@ -0,0 +1,5 @@
+-- Rows stored before readings were tagged with a location are assigned to
+-- 'default'; rename them to the configured location afterwards.
+ALTER TABLE air_quality ADD COLUMN IF NOT EXISTS location TEXT;
+UPDATE air_quality SET location = 'default' WHERE location IS NULL;
+ALTER TABLE air_quality ALTER COLUMN location SET NOT NULL;
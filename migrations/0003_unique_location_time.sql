@@ -0,0 +1,18 @@
+-- Replays before the key existed may have stored an hour more than once.
+-- Keep one copy so the constraint can be added.
+DELETE FROM air_quality a
+USING air_quality b
+WHERE a.location = b.location
+  AND a._time = b._time
+  AND a.ctid < b.ctid;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'air_quality_location_time_key'
+    ) THEN
+        ALTER TABLE air_quality
+            ADD CONSTRAINT air_quality_location_time_key UNIQUE (location, _time);
+    END IF;
+END
+$$;
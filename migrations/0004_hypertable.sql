@@ -0,0 +1,4 @@
+CREATE EXTENSION IF NOT EXISTS timescaledb;
+
+-- Existing rows are moved into chunks, which can take a while on a large table
+SELECT create_hypertable('air_quality', '_time', if_not_exists => TRUE, migrate_data => TRUE);
@@ -0,0 +1,2 @@
+-- Serves the latest reading per location, used by gap filling
+CREATE INDEX IF NOT EXISTS air_quality_location_time_idx ON air_quality (location, _time DESC);
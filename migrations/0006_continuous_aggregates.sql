@@ -0,0 +1,144 @@
+-- Daily and weekly rollups per location, kept up to date by Timescale.
+-- Created without data so this can run inside the migration's transaction;
+-- `refresh-aggregates` fills in history, the policies keep recent buckets current.
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS air_quality_daily_stats
+WITH (timescaledb.continuous) AS
+SELECT
+    location,
+    time_bucket(INTERVAL '1 day', _time) AS bucket,
+    count(*) AS hours,
+    min(pm10) AS pm10_min,
+    avg(pm10) AS pm10_mean,
+    max(pm10) AS pm10_max,
+    min(pm2_5) AS pm2_5_min,
+    avg(pm2_5) AS pm2_5_mean,
+    max(pm2_5) AS pm2_5_max,
+    min(carbon_monoxide) AS carbon_monoxide_min,
+    avg(carbon_monoxide) AS carbon_monoxide_mean,
+    max(carbon_monoxide) AS carbon_monoxide_max,
+    min(carbon_dioxide) AS carbon_dioxide_min,
+    avg(carbon_dioxide) AS carbon_dioxide_mean,
+    max(carbon_dioxide) AS carbon_dioxide_max,
+    min(nitrogen_dioxide) AS nitrogen_dioxide_min,
+    avg(nitrogen_dioxide) AS nitrogen_dioxide_mean,
+    max(nitrogen_dioxide) AS nitrogen_dioxide_max,
+    min(sulphur_dioxide) AS sulphur_dioxide_min,
+    avg(sulphur_dioxide) AS sulphur_dioxide_mean,
+    max(sulphur_dioxide) AS sulphur_dioxide_max,
+    min(ozone) AS ozone_min,
+    avg(ozone) AS ozone_mean,
+    max(ozone) AS ozone_max,
+    min(methane) AS methane_min,
+    avg(methane) AS methane_mean,
+    max(methane) AS methane_max,
+    min(uv_index) AS uv_index_min,
+    avg(uv_index) AS uv_index_mean,
+    max(uv_index) AS uv_index_max,
+    min(dust) AS dust_min,
+    avg(dust) AS dust_mean,
+    max(dust) AS dust_max,
+    min(aerosol_optical_depth) AS aerosol_optical_depth_min,
+    avg(aerosol_optical_depth) AS aerosol_optical_depth_mean,
+    max(aerosol_optical_depth) AS aerosol_optical_depth_max,
+    min(us_aqi) AS us_aqi_min,
+    avg(us_aqi) AS us_aqi_mean,
+    max(us_aqi) AS us_aqi_max
+FROM air_quality
+GROUP BY location, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('air_quality_daily_stats',
+    start_offset => INTERVAL '3 days',
+    end_offset => INTERVAL '1 hour',
+    schedule_interval => INTERVAL '1 hour',
+    if_not_exists => TRUE);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS air_quality_weekly_stats
+WITH (timescaledb.continuous) AS
+SELECT
+    location,
+    time_bucket(INTERVAL '7 days', _time) AS bucket,
+    count(*) AS hours,
+    min(pm10) AS pm10_min,
+    avg(pm10) AS pm10_mean,
+    max(pm10) AS pm10_max,
+    min(pm2_5) AS pm2_5_min,
+    avg(pm2_5) AS pm2_5_mean,
+    max(pm2_5) AS pm2_5_max,
+    min(carbon_monoxide) AS carbon_monoxide_min,
+    avg(carbon_monoxide) AS carbon_monoxide_mean,
+    max(carbon_monoxide) AS carbon_monoxide_max,
+    min(carbon_dioxide) AS carbon_dioxide_min,
+    avg(carbon_dioxide) AS carbon_dioxide_mean,
+    max(carbon_dioxide) AS carbon_dioxide_max,
+    min(nitrogen_dioxide) AS nitrogen_dioxide_min,
+    avg(nitrogen_dioxide) AS nitrogen_dioxide_mean,
+    max(nitrogen_dioxide) AS nitrogen_dioxide_max,
+    min(sulphur_dioxide) AS sulphur_dioxide_min,
+    avg(sulphur_dioxide) AS sulphur_dioxide_mean,
+    max(sulphur_dioxide) AS sulphur_dioxide_max,
+    min(ozone) AS ozone_min,
+    avg(ozone) AS ozone_mean,
+    max(ozone) AS ozone_max,
+    min(methane) AS methane_min,
+    avg(methane) AS methane_mean,
+    max(methane) AS methane_max,
+    min(uv_index) AS uv_index_min,
+    avg(uv_index) AS uv_index_mean,
+    max(uv_index) AS uv_index_max,
+    min(dust) AS dust_min,
+    avg(dust) AS dust_mean,
+    max(dust) AS dust_max,
+    min(aerosol_optical_depth) AS aerosol_optical_depth_min,
+    avg(aerosol_optical_depth) AS aerosol_optical_depth_mean,
+    max(aerosol_optical_depth) AS aerosol_optical_depth_max,
+    min(us_aqi) AS us_aqi_min,
+    avg(us_aqi) AS us_aqi_mean,
+    max(us_aqi) AS us_aqi_max
+FROM air_quality
+GROUP BY location, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('air_quality_weekly_stats',
+    start_offset => INTERVAL '3 weeks',
+    end_offset => INTERVAL '1 hour',
+    schedule_interval => INTERVAL '1 hour',
+    if_not_exists => TRUE);
+
+-- The dominant pollutant is the one whose mean is furthest above its WHO
+-- 2021 24-hour guideline (8-hour for ozone), in µg/m³. It is worked out here
+-- rather than in the aggregates so the guidelines can change without
+-- rebuilding them.
+
+CREATE OR REPLACE VIEW air_quality_daily AS
+SELECT s.*, (
+    SELECT p.pollutant FROM (VALUES
+        ('pm2_5', s.pm2_5_mean / 15),
+        ('pm10', s.pm10_mean / 45),
+        ('nitrogen_dioxide', s.nitrogen_dioxide_mean / 25),
+        ('sulphur_dioxide', s.sulphur_dioxide_mean / 40),
+        ('ozone', s.ozone_mean / 100),
+        ('carbon_monoxide', s.carbon_monoxide_mean / 4000)
+    ) AS p (pollutant, ratio)
+    WHERE p.ratio IS NOT NULL
+    ORDER BY p.ratio DESC
+    LIMIT 1
+) AS dominant_pollutant
+FROM air_quality_daily_stats s;
+
+CREATE OR REPLACE VIEW air_quality_weekly AS
+SELECT s.*, (
+    SELECT p.pollutant FROM (VALUES
+        ('pm2_5', s.pm2_5_mean / 15),
+        ('pm10', s.pm10_mean / 45),
+        ('nitrogen_dioxide', s.nitrogen_dioxide_mean / 25),
+        ('sulphur_dioxide', s.sulphur_dioxide_mean / 40),
+        ('ozone', s.ozone_mean / 100),
+        ('carbon_monoxide', s.carbon_monoxide_mean / 4000)
+    ) AS p (pollutant, ratio)
+    WHERE p.ratio IS NOT NULL
+    ORDER BY p.ratio DESC
+    LIMIT 1
+) AS dominant_pollutant
+FROM air_quality_weekly_stats s;
@@ -0,0 +1,32 @@
+-- The hourly variables are chosen in config now, so any of the well-known
+-- columns can be left empty, and variables without a column of their own
+-- are kept in `extra`.
+--
+-- Compression is switched off for the change, as in 0007; `db migrate`
+-- turns it back on afterwards.
+
+DO $$
+BEGIN
+    IF (
+        SELECT compression_enabled FROM timescaledb_information.hypertables
+        WHERE hypertable_name = 'air_quality'
+    ) THEN
+        PERFORM remove_compression_policy('air_quality', if_exists => true);
+        PERFORM decompress_chunk(c, if_compressed => true) FROM show_chunks('air_quality') c;
+        ALTER TABLE air_quality SET (timescaledb.compress = false);
+    END IF;
+END
+$$;
+
+ALTER TABLE air_quality ALTER COLUMN pm10 DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN pm2_5 DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN carbon_monoxide DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN nitrogen_dioxide DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN sulphur_dioxide DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN ozone DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN uv_index DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN dust DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN aerosol_optical_depth DROP NOT NULL;
+ALTER TABLE air_quality ALTER COLUMN us_aqi DROP NOT NULL;
+
+ALTER TABLE air_quality ADD COLUMN IF NOT EXISTS extra JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+-- Weather for the same locations and hours as air_quality, so the two can
+-- be joined on (location, _time)
+CREATE TABLE IF NOT EXISTS weather (
+    location TEXT NOT NULL,
+    _time TIMESTAMPTZ NOT NULL,
+    temperature_2m DOUBLE PRECISION NULL,
+    relative_humidity_2m DOUBLE PRECISION NULL,
+    wind_speed_10m DOUBLE PRECISION NULL,
+    wind_direction_10m DOUBLE PRECISION NULL,
+    precipitation DOUBLE PRECISION NULL,
+    boundary_layer_height DOUBLE PRECISION NULL,
+    insert_time TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT weather_location_time_key UNIQUE (location, _time)
+);
+
+SELECT create_hypertable('weather', '_time', if_not_exists => TRUE);
@@ -0,0 +1,29 @@
+-- Predicted readings, one row per location, issue and forecast hour. Join to
+-- air_quality on (location, _time) to compare with what was observed.
+CREATE TABLE IF NOT EXISTS air_quality_forecast (
+    location TEXT NOT NULL,
+    issue_time TIMESTAMPTZ NOT NULL,
+    _time TIMESTAMPTZ NOT NULL,
+    lead_hours INTEGER NOT NULL,
+    pm10 DOUBLE PRECISION NULL,
+    pm2_5 DOUBLE PRECISION NULL,
+    carbon_monoxide DOUBLE PRECISION NULL,
+    carbon_dioxide DOUBLE PRECISION NULL,
+    nitrogen_dioxide DOUBLE PRECISION NULL,
+    sulphur_dioxide DOUBLE PRECISION NULL,
+    ozone DOUBLE PRECISION NULL,
+    methane DOUBLE PRECISION NULL,
+    uv_index DOUBLE PRECISION NULL,
+    dust DOUBLE PRECISION NULL,
+    aerosol_optical_depth DOUBLE PRECISION NULL,
+    us_aqi BIGINT NULL,
+    extra JSONB NOT NULL DEFAULT '{}',
+    insert_time TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT air_quality_forecast_location_issue_time_key UNIQUE (location, issue_time, _time)
+);
+
+SELECT create_hypertable('air_quality_forecast', '_time', if_not_exists => TRUE);
+
+-- Serves matching forecasts to observations by location and hour
+CREATE INDEX IF NOT EXISTS air_quality_forecast_location_time_idx
+    ON air_quality_forecast (location, _time DESC, lead_hours);
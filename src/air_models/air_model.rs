@@ -1,11 +1,18 @@
 use async_trait::async_trait;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgArguments, Postgres};
+use sqlx::query::Query;
+use sqlx::types::Json;
 use sqlx::PgPool;
+use std::collections::BTreeMap;
 use std::fmt;
 use tracing::info;
 
-use crate::traits::data_loader::Persistable;
+use crate::air_models::time_format;
+use crate::error::{Error, Result};
+use crate::traits::data_fetcher::{FromColumns, HourlyRecord};
+use crate::traits::data_loader::{rounds, ConflictStrategy, Persistable};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawAirQuality {
@@ -19,26 +26,37 @@ pub struct RawAirQuality {
     pub hourly: RawHourlyData,
 }
 
+/// Variables with columns of their own; the rest go to `extra`.
+pub const WELL_KNOWN_VARIABLES: [&str; 12] = [
+    "pm10",
+    "pm2_5",
+    "carbon_monoxide",
+    "carbon_dioxide",
+    "nitrogen_dioxide",
+    "sulphur_dioxide",
+    "ozone",
+    "methane",
+    "uv_index",
+    "dust",
+    "aerosol_optical_depth",
+    "us_aqi",
+];
+
+/// One column per requested variable, keyed by its Open-Meteo name.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawHourlyData {
-    pub time: Vec<String>,
-    pub pm10: Vec<Option<f64>>,
-    pub pm2_5: Vec<Option<f64>>,
-    pub carbon_monoxide: Vec<Option<f64>>,
-    pub carbon_dioxide: Vec<Option<f64>>,
-    pub nitrogen_dioxide: Vec<Option<f64>>,
-    pub sulphur_dioxide: Vec<Option<f64>>,
-    pub ozone: Vec<Option<f64>>,
-    pub methane: Vec<Option<f64>>,
-    pub uv_index: Vec<Option<f64>>,
-    pub dust: Vec<Option<f64>>,
-    pub aerosol_optical_depth: Vec<Option<f64>>,
-    pub us_aqi: Vec<Option<f64>>,
+    #[serde(with = "time_format::open_meteo")]
+    pub time: Vec<NaiveDateTime>,
+    #[serde(flatten)]
+    pub variables: BTreeMap<String, Vec<Option<f64>>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AirQualityHourly {
-    pub time: String,
+    pub location: String,
+    /// The hour this reading is for
+    #[serde(with = "time_format::wire")]
+    pub time: DateTime<Utc>,
     pub pm10: Option<f64>,
     pub pm2_5: Option<f64>,
     pub carbon_monoxide: Option<f64>,
@@ -51,107 +69,174 @@ pub struct AirQualityHourly {
     pub dust: Option<f64>,
     pub aerosol_optical_depth: Option<f64>,
     pub us_aqi: Option<f64>,
+    /// Requested variables without a column of their own, e.g. pollen counts
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct AirQuality {
-    pub hourly: Vec<AirQualityHourly>,
+impl FromColumns for AirQualityHourly {
+    fn empty(location: &str, time: DateTime<Utc>) -> Self {
+        AirQualityHourly {
+            location: location.to_string(),
+            time,
+            pm10: None,
+            pm2_5: None,
+            carbon_monoxide: None,
+            carbon_dioxide: None,
+            nitrogen_dioxide: None,
+            sulphur_dioxide: None,
+            ozone: None,
+            methane: None,
+            uv_index: None,
+            dust: None,
+            aerosol_optical_depth: None,
+            us_aqi: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    fn set(&mut self, variable: &str, value: Option<f64>) {
+        let field = match variable {
+            "pm10" => &mut self.pm10,
+            "pm2_5" => &mut self.pm2_5,
+            "carbon_monoxide" => &mut self.carbon_monoxide,
+            "carbon_dioxide" => &mut self.carbon_dioxide,
+            "nitrogen_dioxide" => &mut self.nitrogen_dioxide,
+            "sulphur_dioxide" => &mut self.sulphur_dioxide,
+            "ozone" => &mut self.ozone,
+            "methane" => &mut self.methane,
+            "uv_index" => &mut self.uv_index,
+            "dust" => &mut self.dust,
+            "aerosol_optical_depth" => &mut self.aerosol_optical_depth,
+            "us_aqi" => &mut self.us_aqi,
+            _ => {
+                if let Some(value) = value {
+                    self.extra.insert(variable.to_string(), value);
+                }
+                return;
+            }
+        };
+        *field = value;
+    }
 }
 
-impl From<RawAirQuality> for AirQuality {
-    fn from(raw: RawAirQuality) -> Self {
-        let len = raw.hourly.time.len();
-
-        let hourly = (0..len)
-            .map(|i| AirQualityHourly {
-                time: raw.hourly.time.get(i).cloned().unwrap_or_default(),
-                pm10: raw.hourly.pm10.get(i).copied().flatten(),
-                pm2_5: raw.hourly.pm2_5.get(i).copied().flatten(),
-                carbon_monoxide: raw.hourly.carbon_monoxide.get(i).copied().flatten(),
-                carbon_dioxide: raw.hourly.carbon_dioxide.get(i).copied().flatten(),
-                nitrogen_dioxide: raw.hourly.nitrogen_dioxide.get(i).copied().flatten(),
-                sulphur_dioxide: raw.hourly.sulphur_dioxide.get(i).copied().flatten(),
-                ozone: raw.hourly.ozone.get(i).copied().flatten(),
-                methane: raw.hourly.methane.get(i).copied().flatten(),
-                uv_index: raw.hourly.uv_index.get(i).copied().flatten(),
-                dust: raw.hourly.dust.get(i).copied().flatten(),
-                aerosol_optical_depth: raw.hourly.aerosol_optical_depth.get(i).copied().flatten(),
-                us_aqi: raw.hourly.us_aqi.get(i).copied().flatten(),
-            })
-            .collect();
+impl HourlyRecord for AirQualityHourly {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+}
+
+impl RawHourlyData {
+    /// One record per timestamp for `location`, shifted to UTC by
+    /// `utc_offset_seconds`. Fails on a column without one value per timestamp.
+    pub(crate) fn into_records<R: FromColumns>(
+        self,
+        utc_offset_seconds: i32,
+        location: &str,
+    ) -> Result<Vec<R>> {
+        for (variable, values) in &self.variables {
+            if values.len() != self.time.len() {
+                return Err(Error::MisalignedColumn {
+                    variable: variable.clone(),
+                    expected: self.time.len(),
+                    found: values.len(),
+                });
+            }
+        }
+        let offset = TimeDelta::seconds(utc_offset_seconds.into());
 
-        AirQuality { hourly: hourly }
+        let mut records: Vec<R> = self
+            .time
+            .iter()
+            .map(|local| R::empty(location, (*local - offset).and_utc()))
+            .collect();
+        for (variable, values) in &self.variables {
+            for (record, value) in records.iter_mut().zip(values) {
+                record.set(variable, *value);
+            }
+        }
+        Ok(records)
     }
 }
 
+/// UNNEST parameters for [`bind_readings`], numbered from `$first`.
+pub(crate) fn reading_params(first: usize) -> String {
+    let types =
+        std::iter::repeat_n("float8", WELL_KNOWN_VARIABLES.len() - 1).chain(["int8", "jsonb"]);
+    types
+        .enumerate()
+        .map(|(i, t)| format!("${}::{}[]", first + i, t))
+        .collect::<Vec<_>>()
+        .join(",\n            ")
+}
+
+/// One array per well-known variable, followed by `extra`.
+pub(crate) fn bind_readings<'q>(
+    query: Query<'q, Postgres, PgArguments>,
+    rows: &[&'q AirQualityHourly],
+) -> Query<'q, Postgres, PgArguments> {
+    let column = |value: fn(&AirQualityHourly) -> Option<f64>| -> Vec<Option<f64>> {
+        rows.iter().map(|r| value(r)).collect()
+    };
+    let us_aqis: Vec<Option<i64>> = rows.iter().map(|r| r.us_aqi.map(|v| v as i64)).collect();
+    let extras: Vec<Json<&BTreeMap<String, f64>>> = rows.iter().map(|r| Json(&r.extra)).collect();
+
+    query
+        .bind(column(|r| r.pm10))
+        .bind(column(|r| r.pm2_5))
+        .bind(column(|r| r.carbon_monoxide))
+        .bind(column(|r| r.carbon_dioxide))
+        .bind(column(|r| r.nitrogen_dioxide))
+        .bind(column(|r| r.sulphur_dioxide))
+        .bind(column(|r| r.ozone))
+        .bind(column(|r| r.methane))
+        .bind(column(|r| r.uv_index))
+        .bind(column(|r| r.dust))
+        .bind(column(|r| r.aerosol_optical_depth))
+        .bind(us_aqis)
+        .bind(extras)
+}
+
 #[async_trait]
 impl Persistable for Vec<AirQualityHourly> {
-    async fn save_to_db(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+    async fn save_to_db(&self, pool: &PgPool, strategy: ConflictStrategy) -> Result<()> {
         if self.is_empty() {
             return Ok(());
         }
-        info!(target: "consumer", "Parsing time series data");
-        let times: Vec<DateTime<Utc>> = self
-            .iter()
-            .map(|r| {
-                let naive = NaiveDateTime::parse_from_str(&r.time, "%Y-%m-%dT%H:%M")
-                    .expect("Invalid Time Format");
-                naive.and_utc()
-            })
-            .collect();
-
-        let pm10s: Vec<Option<f64>> = self.iter().map(|r| r.pm10).collect();
-        let pm2_5s: Vec<Option<f64>> = self.iter().map(|r| r.pm2_5).collect();
-        let carbon_monoxides: Vec<Option<f64>> = self.iter().map(|r| r.carbon_monoxide).collect();
-        let carbon_dioxides: Vec<Option<f64>> = self.iter().map(|r| r.carbon_dioxide).collect();
-        let nitrogen_dioxides: Vec<Option<f64>> = self.iter().map(|r| r.nitrogen_dioxide).collect();
-        let sulphur_dioxides: Vec<Option<f64>> = self.iter().map(|r| r.sulphur_dioxide).collect();
-        let ozones: Vec<Option<f64>> = self.iter().map(|r| r.ozone).collect();
-        let methanes: Vec<Option<f64>> = self.iter().map(|r| r.methane).collect();
-        let uv_indexes: Vec<Option<f64>> = self.iter().map(|r| r.uv_index).collect();
-        let dusts: Vec<Option<f64>> = self.iter().map(|r| r.dust).collect();
-        let aods: Vec<Option<f64>> = self.iter().map(|r| r.aerosol_optical_depth).collect();
-        let us_aqis: Vec<Option<i64>> = self.iter().map(|r| r.us_aqi.map(|v| v as i64)).collect();
-
-        let query = r#"
+        let query = format!(
+            r#"
         INSERT INTO air_quality (
-            _time, pm10, pm2_5, carbon_monoxide, carbon_dioxide,
-            nitrogen_dioxide, sulphur_dioxide, ozone, methane,
-            uv_index, dust, aerosol_optical_depth, us_aqi
+            location, _time, {}, extra
         )
         SELECT * FROM UNNEST(
-            $1::timestamp[],
-            $2::float8[],
-            $3::float8[],
-            $4::float8[],
-            $5::float8[],
-            $6::float8[],
-            $7::float8[],
-            $8::float8[],
-            $9::float8[],
-            $10::float8[],
-            $11::float8[],
-            $12::float8[],
-            $13::int8[]
+            $1::text[],
+            $2::timestamptz[],
+            {}
         )
-    "#;
-
-        sqlx::query(query)
-            .bind(&times)
-            .bind(&pm10s)
-            .bind(&pm2_5s)
-            .bind(&carbon_monoxides)
-            .bind(&carbon_dioxides)
-            .bind(&nitrogen_dioxides)
-            .bind(&sulphur_dioxides)
-            .bind(&ozones)
-            .bind(&methanes)
-            .bind(&uv_indexes)
-            .bind(&dusts)
-            .bind(&aods)
-            .bind(&us_aqis)
-            .execute(pool)
-            .await?;
+        {}
+    "#,
+            WELL_KNOWN_VARIABLES.join(", "),
+            reading_params(3),
+            strategy.on_conflict(
+                "air_quality",
+                &["location", "_time"],
+                &WELL_KNOWN_VARIABLES,
+                &["extra"]
+            )
+        );
+
+        let mut tx = pool.begin().await?;
+        for rows in rounds(self) {
+            let locations: Vec<&str> = rows.iter().map(|r| r.location.as_str()).collect();
+            let times: Vec<DateTime<Utc>> = rows.iter().map(|r| r.time).collect();
+            let insert = sqlx::query(&query).bind(&locations).bind(&times);
+            bind_readings(insert, &rows).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
         info!(target: "consumer", "Data ingested");
         Ok(())
     }
@@ -159,7 +244,7 @@ impl Persistable for Vec<AirQualityHourly> {
 
 impl fmt::Display for AirQualityHourly {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Air Quality at {}", self.time)?;
+        writeln!(f, "Air Quality at {} ({})", self.time, self.location)?;
         writeln!(f, "-----------------------------------------")?;
         writeln!(f, "PM10:                 {:?}", self.pm10)?;
         writeln!(f, "PM2.5:                {:?}", self.pm2_5)?;
@@ -173,6 +258,98 @@ impl fmt::Display for AirQualityHourly {
         writeln!(f, "Dust:                 {:?}", self.dust)?;
         writeln!(f, "Aerosol Optical Depth:{:?}", self.aerosol_optical_depth)?;
         writeln!(f, "US AQI:               {:?}", self.us_aqi)?;
+        for (variable, value) in &self.extra {
+            writeln!(f, "{:<22}{}", format!("{}:", variable), value)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn raw(utc_offset_seconds: i32, hourly: Value) -> RawAirQuality {
+        serde_json::from_value(json!({
+            "latitude": 52.52,
+            "longitude": 13.41,
+            "elevation": 38.0,
+            "generationtime_ms": 0.1,
+            "utc_offset_seconds": utc_offset_seconds,
+            "timezone": "GMT",
+            "timezone_abbreviation": "GMT",
+            "hourly": hourly,
+        }))
+        .unwrap()
+    }
+
+    fn records(raw: RawAirQuality) -> Result<Vec<AirQualityHourly>> {
+        raw.hourly.into_records(raw.utc_offset_seconds, "berlin")
+    }
+
+    #[test]
+    fn ragged_columns_are_rejected() {
+        let raw = raw(
+            0,
+            json!({
+                "time": ["2024-01-01T00:00", "2024-01-01T01:00"],
+                "pm10": [1.0, 2.0],
+                "ozone": [3.0],
+            }),
+        );
+        match records(raw) {
+            Err(Error::MisalignedColumn {
+                variable,
+                expected,
+                found,
+            }) => {
+                assert_eq!(variable, "ozone");
+                assert_eq!((expected, found), (2, 1));
+            }
+            other => panic!("expected a misaligned column, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn local_times_are_shifted_to_utc() {
+        // Central European Summer Time, two hours ahead of UTC
+        let raw = raw(
+            7200,
+            json!({
+                "time": ["2024-03-31T03:00", "2024-03-31T04:00"],
+                "pm10": [1.0, null],
+            }),
+        );
+        let hourly = records(raw).unwrap();
+        let times: Vec<DateTime<Utc>> = hourly.iter().map(|r| r.time).collect();
+        assert_eq!(
+            times,
+            [
+                "2024-03-31T01:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+                "2024-03-31T02:00:00Z".parse().unwrap(),
+            ]
+        );
+        assert_eq!(hourly[0].pm10, Some(1.0));
+        assert_eq!(hourly[1].pm10, None);
+    }
+
+    #[test]
+    fn unknown_variables_go_to_extra() {
+        let raw = raw(
+            0,
+            json!({
+                "time": ["2024-01-01T00:00"],
+                "us_aqi": [42.0],
+                "birch_pollen": [5.0],
+                "grass_pollen": [null],
+            }),
+        );
+        let hourly = records(raw).unwrap();
+        assert_eq!(hourly[0].us_aqi, Some(42.0));
+        assert_eq!(
+            hourly[0].extra,
+            BTreeMap::from([("birch_pollen".to_string(), 5.0)])
+        );
+    }
+}
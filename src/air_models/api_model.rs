@@ -1,73 +1,190 @@
-use crate::air_models::air_model::AirQuality;
+use crate::air_models::forecast_model::AirQualityForecast;
 use crate::air_models::{AirQualityHourly, RawAirQuality};
+use crate::api_client::{latest_until, ApiClient, TIMEZONE};
+use crate::config::LocationConfig;
+use crate::error::{Error, Result};
+use crate::replay::{historical_name, recent_name, Recorded};
 use crate::traits::data_fetcher::DataFetcher;
 use async_trait::async_trait;
-use chrono::{NaiveDateTime, TimeZone, Utc};
-use reqwest::Client;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+
+/// Start of the hour a forecast fetched at `fetched_at` was issued in.
+fn issue_time(fetched_at: DateTime<Utc>) -> DateTime<Utc> {
+    fetched_at
+        .duration_trunc(TimeDelta::hours(1))
+        .expect("an hour fits any timestamp")
+}
 
 pub struct APIFetcher {
-    pub client: Client,
-    pub latitude: f64,
-    pub longitude: f64,
+    pub http: ApiClient,
+    pub location: LocationConfig,
+}
+
+impl APIFetcher {
+    fn url(&self, range: &str) -> String {
+        let api = &self.http.api;
+        format!(
+            "{}?latitude={}&longitude={}&{}&timezone={}&hourly={}&domains={}",
+            api.air_quality_url(),
+            self.location.latitude,
+            self.location.longitude,
+            range,
+            TIMEZONE,
+            api.hourly.join(","),
+            api.domains.as_str()
+        )
+    }
+
+    fn recent_url(&self) -> String {
+        self.url("past_hours=1&forecast_hours=1")
+    }
+}
+
+impl Recorded for APIFetcher {
+    type Raw = RawAirQuality;
+    type Record = AirQualityHourly;
+
+    const RECENT_PREFIX: &'static str = "air-quality_recent_";
+    const HISTORICAL_PREFIXES: &'static [&'static str] = &["air-quality_"];
+
+    fn recent(
+        raw: RawAirQuality,
+        location: &str,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<Vec<AirQualityHourly>> {
+        // Filter out future timestamps
+        Ok(latest_until(
+            raw.hourly.into_records(raw.utc_offset_seconds, location)?,
+            fetched_at,
+        ))
+    }
+
+    fn historical(raw: RawAirQuality, location: &str) -> Result<Vec<AirQualityHourly>> {
+        raw.hourly.into_records(raw.utc_offset_seconds, location)
+    }
 }
 
 #[async_trait]
 impl DataFetcher for APIFetcher {
-    async fn fetch_recent(
-        &self,
-    ) -> Result<Vec<AirQualityHourly>, Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!(
-            "https://air-quality-api.open-meteo.com/v1/air-quality?latitude={}&longitude={}&past_hours=1&forecas&hourly=pm10,pm2_5,carbon_monoxide,carbon_dioxide,nitrogen_dioxide,sulphur_dioxide,ozone,methane,uv_index,dust,aerosol_optical_depth,us_aqi",
-            self.latitude, self.longitude
-        );
+    type Record = AirQualityHourly;
 
-        let response = self.client.get(url).send().await?;
-        let raw_data: RawAirQuality = response.json().await?;
-        let hourly_data: AirQuality = raw_data.into();
+    fn location(&self) -> &LocationConfig {
+        &self.location
+    }
 
+    async fn fetch_recent(&self) -> Result<Vec<AirQualityHourly>> {
+        let url = self.recent_url();
         let now = Utc::now();
 
-        // Filter out future timestamps by parsing time string
-        let filtered: Vec<AirQualityHourly> = hourly_data
+        let name = recent_name(Self::RECENT_PREFIX, now);
+        let raw_data = self.http.get(&url, &self.location.name, &name).await?;
+        Self::recent(raw_data, &self.location.name, now)
+    }
+
+    async fn fetch_historical(
+        &self,
+        start_date: &str,
+        end_date: &str,
+    ) -> Result<Vec<AirQualityHourly>> {
+        let url = self.url(&format!("start_date={}&end_date={}", start_date, end_date));
+
+        let name = historical_name(Self::HISTORICAL_PREFIXES[0], start_date, end_date);
+        let raw_data = self.http.get(&url, &self.location.name, &name).await?;
+        Self::historical(raw_data, &self.location.name)
+    }
+}
+
+pub struct ForecastFetcher {
+    pub fetcher: APIFetcher,
+}
+
+/// Hours that are not after the issue time are left out.
+impl Recorded for ForecastFetcher {
+    type Raw = RawAirQuality;
+    type Record = AirQualityForecast;
+
+    const RECENT_PREFIX: &'static str = "air-quality_forecast_";
+    const HISTORICAL_PREFIXES: &'static [&'static str] = &[];
+
+    fn recent(
+        raw: RawAirQuality,
+        location: &str,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<Vec<AirQualityForecast>> {
+        let issue_time = issue_time(fetched_at);
+        Ok(raw
             .hourly
+            .into_records::<AirQualityHourly>(raw.utc_offset_seconds, location)?
             .into_iter()
-            .filter_map(|record| {
-                if let Ok(naive_dt) = NaiveDateTime::parse_from_str(&record.time, "%Y-%m-%dT%H:%M")
-                {
-                    let dt_utc = Utc.from_utc_datetime(&naive_dt);
-                    if dt_utc <= now {
-                        Some((dt_utc, record))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .max_by_key(|(dt, _)| *dt)
-            .map(|(_, record)| record)
-            .into_iter()
-            .collect();
+            .filter(|record| record.time > issue_time)
+            .map(|record| AirQualityForecast::new(issue_time, record))
+            .collect())
+    }
+
+    fn historical(_raw: RawAirQuality, _location: &str) -> Result<Vec<AirQualityForecast>> {
+        Err(Error::Unsupported("fetching past forecasts"))
+    }
+}
+
+#[async_trait]
+impl DataFetcher for ForecastFetcher {
+    type Record = AirQualityForecast;
+
+    fn location(&self) -> &LocationConfig {
+        &self.fetcher.location
+    }
 
-        Ok(filtered)
+    /// Hourly forecast for the coming `[api] forecast_days` days.
+    async fn fetch_recent(&self) -> Result<Vec<AirQualityForecast>> {
+        let http = &self.fetcher.http;
+        let location = &self.fetcher.location.name;
+        let url = self
+            .fetcher
+            .url(&format!("forecast_days={}", http.api.forecast_days));
+        let issue_time = issue_time(Utc::now());
+
+        let name = recent_name(Self::RECENT_PREFIX, issue_time);
+        let raw_data = http.get(&url, location, &name).await?;
+        Self::recent(raw_data, location, issue_time)
     }
 
+    /// Open-Meteo only serves the current forecast.
     async fn fetch_historical(
         &self,
-        start_date: &str,
-        end_date: &str,
-    ) -> Result<Vec<AirQualityHourly>, Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!(
-        "https://air-quality-api.open-meteo.com/v1/air-quality?latitude={}&longitude={}&start_date={}&end_date={}&hourly=pm10,pm2_5,carbon_monoxide,carbon_dioxide,nitrogen_dioxide,sulphur_dioxide,ozone,methane,uv_index,dust,aerosol_optical_depth,us_aqi",
-        self.latitude,
-        self.longitude,
-        start_date,
-        end_date
-    );
-
-        let response = self.client.get(url).send().await?;
-        let raw_data: RawAirQuality = response.json().await?;
-        let raw_transformed: AirQuality = raw_data.into();
-        Ok(raw_transformed.hourly)
+        _start_date: &str,
+        _end_date: &str,
+    ) -> Result<Vec<AirQualityForecast>> {
+        Err(Error::Unsupported("fetching past forecasts"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiConfig;
+    use crate::testing::berlin;
+    use std::collections::HashMap;
+
+    #[test]
+    fn recent_url_asks_for_the_last_and_the_next_hour() {
+        let fetcher = APIFetcher {
+            http: ApiClient::new(ApiConfig::default()).unwrap(),
+            location: berlin(),
+        };
+        let url = reqwest::Url::parse(&fetcher.recent_url()).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(
+            url.as_str().split('?').next(),
+            Some("https://air-quality-api.open-meteo.com/v1/air-quality")
+        );
+        assert_eq!(query["latitude"], "52.52");
+        assert_eq!(query["longitude"], "13.41");
+        assert_eq!(query["past_hours"], "1");
+        assert_eq!(query["forecast_hours"], "1");
+        assert_eq!(query["timezone"], TIMEZONE);
+        assert_eq!(query["hourly"], ApiConfig::default().hourly.join(","));
+        assert_eq!(query["domains"], "auto");
+        assert_eq!(query.len(), 7);
     }
 }
@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::air_models::air_model::{
+    bind_readings, reading_params, AirQualityHourly, WELL_KNOWN_VARIABLES,
+};
+use crate::air_models::time_format;
+use crate::error::Result;
+use crate::traits::data_fetcher::HourlyRecord;
+use crate::traits::data_loader::{rounds, ConflictStrategy, Persistable};
+
+/// A predicted reading, tagged with when the forecast was issued.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AirQualityForecast {
+    /// Start of the hour the forecast was fetched in
+    #[serde(with = "time_format::wire")]
+    pub issue_time: DateTime<Utc>,
+    /// Hours between `issue_time` and the hour the reading is for
+    pub lead_hours: i32,
+    #[serde(flatten)]
+    pub reading: AirQualityHourly,
+}
+
+impl AirQualityForecast {
+    pub fn new(issue_time: DateTime<Utc>, reading: AirQualityHourly) -> Self {
+        AirQualityForecast {
+            issue_time,
+            lead_hours: (reading.time - issue_time).num_hours() as i32,
+            reading,
+        }
+    }
+}
+
+impl HourlyRecord for AirQualityForecast {
+    fn location(&self) -> &str {
+        &self.reading.location
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.reading.time
+    }
+}
+
+#[async_trait]
+impl Persistable for Vec<AirQualityForecast> {
+    async fn save_to_db(&self, pool: &PgPool, strategy: ConflictStrategy) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let query = format!(
+            r#"
+        INSERT INTO air_quality_forecast (
+            location, issue_time, _time, lead_hours, {}, extra
+        )
+        SELECT * FROM UNNEST(
+            $1::text[],
+            $2::timestamptz[],
+            $3::timestamptz[],
+            $4::int4[],
+            {}
+        )
+        {}
+    "#,
+            WELL_KNOWN_VARIABLES.join(", "),
+            reading_params(5),
+            strategy.on_conflict(
+                "air_quality_forecast",
+                &["location", "issue_time", "_time"],
+                &WELL_KNOWN_VARIABLES,
+                &["extra"]
+            )
+        );
+
+        let mut tx = pool.begin().await?;
+        for rows in rounds(self) {
+            let locations: Vec<&str> = rows.iter().map(|r| r.reading.location.as_str()).collect();
+            let issue_times: Vec<DateTime<Utc>> = rows.iter().map(|r| r.issue_time).collect();
+            let times: Vec<DateTime<Utc>> = rows.iter().map(|r| r.reading.time).collect();
+            let lead_hours: Vec<i32> = rows.iter().map(|r| r.lead_hours).collect();
+            let readings: Vec<&AirQualityHourly> = rows.iter().map(|r| &r.reading).collect();
+
+            let insert = sqlx::query(&query)
+                .bind(&locations)
+                .bind(&issue_times)
+                .bind(&times)
+                .bind(&lead_hours);
+            bind_readings(insert, &readings).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        info!(target: "consumer", "Forecast ingested");
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 pub mod air_model;
 pub mod api_model;
+pub mod forecast_model;
+pub mod time_format;
 
 pub use air_model::{AirQualityHourly, RawAirQuality};
-pub use api_model::APIFetcher;
+pub use api_model::{APIFetcher, ForecastFetcher};
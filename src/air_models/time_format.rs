@@ -0,0 +1,129 @@
+//! Serde formats for the hour a reading is for.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+use crate::error::{Error, Result};
+
+/// Format of Open-Meteo's `time` values, which carry no zone.
+pub const OPEN_METEO_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+pub fn parse_open_meteo(value: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, OPEN_METEO_FORMAT).map_err(|source| Error::TimeParse {
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// Parses a `start_date`/`end_date` request parameter.
+pub fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|source| Error::TimeParse {
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// Local wall-clock times in the requested timezone.
+pub mod open_meteo {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(times: &[NaiveDateTime], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(
+            times
+                .iter()
+                .map(|t| t.format(OPEN_METEO_FORMAT).to_string()),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<NaiveDateTime>, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|value| parse_open_meteo(value).map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+/// RFC 3339 in UTC. Zone-less times from older producers are read as UTC.
+pub mod wire {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(time: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        let value = String::deserialize(d)?;
+        if let Ok(time) = DateTime::parse_from_rfc3339(&value) {
+            return Ok(time.with_timezone(&Utc));
+        }
+        parse_open_meteo(&value)
+            .map(|naive| naive.and_utc())
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use serde::Serialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Reading {
+        #[serde(with = "wire")]
+        time: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize)]
+    struct Hourly {
+        #[serde(with = "open_meteo")]
+        time: Vec<NaiveDateTime>,
+    }
+
+    fn wire_time(value: &str) -> serde_json::Result<DateTime<Utc>> {
+        serde_json::from_value::<Reading>(serde_json::json!({ "time": value }))
+            .map(|reading| reading.time)
+    }
+
+    #[test]
+    fn wire_times_are_written_as_utc() {
+        let reading = Reading {
+            time: "2024-03-31T01:00:00Z".parse().unwrap(),
+        };
+        assert_eq!(
+            serde_json::to_string(&reading).unwrap(),
+            r#"{"time":"2024-03-31T01:00:00Z"}"#
+        );
+    }
+
+    #[test]
+    fn wire_times_with_an_offset_are_read_as_utc() {
+        assert_eq!(
+            wire_time("2024-03-31T03:00:00+02:00").unwrap(),
+            "2024-03-31T01:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn zone_less_wire_times_from_older_producers_are_read_as_utc() {
+        assert_eq!(
+            wire_time("2024-03-31T01:00").unwrap(),
+            "2024-03-31T01:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(wire_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn open_meteo_columns_are_parsed_without_a_zone() {
+        let hourly: Hourly =
+            serde_json::from_str(r#"{"time": ["2024-03-31T01:00", "2024-03-31T02:00"]}"#).unwrap();
+        assert_eq!(
+            hourly.time,
+            [1, 2].map(|hour| NaiveDate::from_ymd_opt(2024, 3, 31)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap())
+        );
+        assert!(serde_json::from_str::<Hourly>(r#"{"time": ["2024-03-31"]}"#).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, Proxy, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::config::ApiConfig;
+use crate::error::{Error, Result};
+use crate::traits::data_fetcher::HourlyRecord;
+
+/// A response has one `utc_offset_seconds` for every hour, which is only right
+/// in a zone without daylight saving.
+pub const TIMEZONE: &str = "GMT";
+
+/// Format of the fetch time in the names of recorded responses.
+pub const RECORDED_AT_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Where the response named `name` for `location` is recorded under `dir`.
+pub fn recording_path(dir: &Path, location: &str, name: &str) -> PathBuf {
+    dir.join(location).join(format!("{}.json", name))
+}
+
+/// The latest reading that is not after `at`.
+pub fn latest_until<R: HourlyRecord>(hourly: Vec<R>, at: DateTime<Utc>) -> Vec<R> {
+    hourly
+        .into_iter()
+        .filter(|record| record.time() <= at)
+        .max_by_key(|record| record.time())
+        .into_iter()
+        .collect()
+}
+
+async fn record(path: &Path, body: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, body).await
+}
+
+/// The body Open-Meteo sends with a 4xx, e.g. for an invalid date range.
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    reason: String,
+}
+
+/// Reads `Retry-After`, which is either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok()
+}
+
+/// HTTP access to the Open-Meteo APIs, shared by every fetcher.
+#[derive(Clone)]
+pub struct ApiClient {
+    pub client: Client,
+    pub api: ApiConfig,
+    /// Directory every successful response body is saved to
+    pub record: Option<PathBuf>,
+}
+
+impl ApiClient {
+    pub fn new(api: ApiConfig) -> Result<Self> {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(api.timeout_secs))
+            .user_agent(&api.user_agent);
+        if let Some(proxy) = &api.proxy {
+            let proxy = Proxy::all(proxy).map_err(|e| {
+                Error::Config(config::ConfigError::Message(format!(
+                    "invalid [api] proxy '{}': {}",
+                    proxy, e
+                )))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        Ok(ApiClient {
+            client: builder.build()?,
+            api,
+            record: None,
+        })
+    }
+
+    /// Doubles every attempt up to the maximum, plus up to half again as jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .api
+            .backoff_base_ms
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.api.backoff_max_ms);
+        let jitter = rand::random_range(0..=delay / 2);
+        Duration::from_millis(delay / 2 + jitter)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, url: &str, location: &str, name: &str) -> Result<T> {
+        let mut request = self.client.get(url);
+        if let Some(apikey) = &self.api.apikey {
+            request = request.query(&[("apikey", apikey)]);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            });
+        }
+
+        let body = response.text().await?;
+        if status.is_success() {
+            if let Some(dir) = &self.record {
+                let path = recording_path(dir, location, name);
+                if let Err(e) = record(&path, &body).await {
+                    warn!(target: "producer",
+                        "[Producer] Failed to record response to {}: {}", path.display(), e
+                    );
+                }
+            }
+            return Ok(serde_json::from_str(&body)?);
+        }
+        match serde_json::from_str::<ApiErrorBody>(&body) {
+            Ok(error) => Err(Error::Api {
+                status,
+                reason: error.reason,
+            }),
+            Err(_) => Err(Error::HttpStatus { status, body }),
+        }
+    }
+
+    /// Retries retryable errors, waiting at least as long as `Retry-After` asks.
+    /// `name` identifies the response when recording.
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        location: &str,
+        name: &str,
+    ) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match self.send(url, location, name).await {
+                Ok(raw_data) => return Ok(raw_data),
+                Err(e) if e.is_retryable() && attempt < self.api.max_retries => {
+                    let mut delay = self.backoff(attempt);
+                    if let Error::RateLimited {
+                        retry_after: Some(retry_after),
+                    } = e
+                    {
+                        delay = delay.max(retry_after);
+                    }
+                    attempt += 1;
+                    warn!(target: "producer",
+                        "[Producer] Request for {} failed (retry {}/{} in {:?}): {}",
+                        location, attempt, self.api.max_retries, delay, e
+                    );
+                    sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        HeaderMap::from_iter([(RETRY_AFTER, retry_after.parse().unwrap())])
+    }
+
+    #[test]
+    fn retry_after_reads_seconds() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    #[test]
+    fn retry_after_reads_http_dates() {
+        let at = (Utc::now() + chrono::TimeDelta::minutes(5)).to_rfc2822();
+        let wait = retry_after(&headers(&at)).unwrap();
+        assert!(wait > Duration::from_secs(240) && wait <= Duration::from_secs(300));
+
+        // A date that has already passed means there is nothing to wait for
+        let past = (Utc::now() - chrono::TimeDelta::minutes(5)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&past)), None);
+    }
+
+    /// Serves `body` with a 200 to every request on a loopback port and
+    /// returns the client pointed at it.
+    pub(crate) async fn serve(body: String) -> ApiClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        ApiClient::new(ApiConfig {
+            base_url: Some(format!("http://{}", addr)),
+            ..ApiConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_successful_responses() {
+        let dir = temp_dir("api_client_record");
+        let mut http = serve(r#"{"a":1}"#.to_string()).await;
+        http.record = Some(dir.clone());
+
+        let url = http.api.air_quality_url();
+        let body: Value = http.get(&url, "berlin", "response").await.unwrap();
+        assert_eq!(body["a"], 1);
+        let recorded = std::fs::read_to_string(recording_path(&dir, "berlin", "response")).unwrap();
+        assert_eq!(recorded, r#"{"a":1}"#);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_to_record_does_not_fail_the_fetch() {
+        // A file where the recording directory should be
+        let file = temp_dir("api_client_blocked");
+        std::fs::write(&file, "").unwrap();
+        let mut http = serve(r#"{"a":1}"#.to_string()).await;
+        http.record = Some(file.clone());
+
+        let url = http.api.air_quality_url();
+        let body: Value = http.get(&url, "berlin", "response").await.unwrap();
+        assert_eq!(body["a"], 1);
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use chrono::{Duration as TimeDuration, NaiveDate, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::air_models::time_format::parse_date;
+use crate::config::Dataset;
+use crate::error::{Error, Result};
+
+/// First day the Open-Meteo air quality archive serves data for.
+pub const AIR_QUALITY_EARLIEST_DATE: &str = "2022-08-01";
+/// First day the Open-Meteo weather archive serves data for.
+pub const WEATHER_EARLIEST_DATE: &str = "1940-01-01";
+/// Largest span the API returns in a single request.
+pub const API_MAX_WINDOW_DAYS: i64 = 92;
+
+pub const DEFAULT_START_DATE: &str = "2023-01-01";
+pub const DEFAULT_WINDOW_DAYS: i64 = 91;
+
+/// An inclusive range of days requested from the API in one call.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateWindow {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Oldest window first
+    Forward,
+    /// Newest window first
+    Backward,
+}
+
+/// An open `end` runs up to the day the backfill was first started.
+#[derive(Debug, Clone, Copy)]
+pub struct BackfillRange {
+    pub start: NaiveDate,
+    pub end: Option<NaiveDate>,
+    pub window_days: i64,
+    pub direction: Direction,
+}
+
+impl BackfillRange {
+    /// Fails when the API cannot serve the range for `dataset`.
+    pub fn new(
+        dataset: Dataset,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        window_days: i64,
+        direction: Direction,
+    ) -> Result<Self> {
+        let earliest = parse_date(earliest_date(dataset))?;
+        let today = Utc::now().date_naive();
+        let start = match start {
+            Some(start) => start,
+            None => parse_date(DEFAULT_START_DATE)?,
+        };
+
+        if start < earliest {
+            return Err(Error::InvalidRange(format!(
+                "start date {} is before the earliest available date {}",
+                start, earliest
+            )));
+        }
+        if let Some(end) = end {
+            if end > today {
+                return Err(Error::InvalidRange(format!(
+                    "end date {} is in the future",
+                    end
+                )));
+            }
+            if end < start {
+                return Err(Error::InvalidRange(format!(
+                    "end date {} is before start date {}",
+                    end, start
+                )));
+            }
+        } else if start > today {
+            return Err(Error::InvalidRange(format!(
+                "start date {} is in the future",
+                start
+            )));
+        }
+        if !(1..=API_MAX_WINDOW_DAYS).contains(&window_days) {
+            return Err(Error::InvalidRange(format!(
+                "window size must be between 1 and {} days, got {}",
+                API_MAX_WINDOW_DAYS, window_days
+            )));
+        }
+
+        Ok(BackfillRange {
+            start,
+            end,
+            window_days,
+            direction,
+        })
+    }
+}
+
+/// Splits `start..=end` into windows of at most `window_days` days. The first
+/// window in `direction` is always a full one.
+pub fn plan_windows(
+    start: NaiveDate,
+    end: NaiveDate,
+    window_days: i64,
+    direction: Direction,
+) -> Vec<DateWindow> {
+    let span = TimeDuration::days(window_days - 1);
+    let mut windows = Vec::new();
+
+    match direction {
+        Direction::Backward => {
+            let mut current_end = end;
+            while current_end >= start {
+                let current_start = (current_end - span).max(start);
+                windows.push(DateWindow {
+                    start: current_start,
+                    end: current_end,
+                });
+                current_end = current_start - TimeDuration::days(1);
+            }
+        }
+        Direction::Forward => {
+            let mut current_start = start;
+            while current_start <= end {
+                let current_end = (current_start + span).min(end);
+                windows.push(DateWindow {
+                    start: current_start,
+                    end: current_end,
+                });
+                current_start = current_end + TimeDuration::days(1);
+            }
+        }
+    }
+    windows
+}
+
+/// Forecasts share the air quality archive.
+fn earliest_date(dataset: Dataset) -> &'static str {
+    match dataset {
+        Dataset::Weather => WEATHER_EARLIEST_DATE,
+        Dataset::AirQuality | Dataset::Forecast => AIR_QUALITY_EARLIEST_DATE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    fn window(start: &str, end: &str) -> DateWindow {
+        DateWindow {
+            start: date(start),
+            end: date(end),
+        }
+    }
+
+    #[test]
+    fn forward_windows_start_full_and_end_short() {
+        let windows = plan_windows(
+            date("2024-01-01"),
+            date("2024-01-08"),
+            3,
+            Direction::Forward,
+        );
+        assert_eq!(
+            windows,
+            [
+                window("2024-01-01", "2024-01-03"),
+                window("2024-01-04", "2024-01-06"),
+                window("2024-01-07", "2024-01-08"),
+            ]
+        );
+    }
+
+    #[test]
+    fn backward_windows_are_anchored_on_the_end() {
+        let windows = plan_windows(
+            date("2024-01-01"),
+            date("2024-01-08"),
+            3,
+            Direction::Backward,
+        );
+        assert_eq!(
+            windows,
+            [
+                window("2024-01-06", "2024-01-08"),
+                window("2024-01-03", "2024-01-05"),
+                window("2024-01-01", "2024-01-02"),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_single_day_is_one_window() {
+        let day = date("2024-01-01");
+        for direction in [Direction::Forward, Direction::Backward] {
+            assert_eq!(
+                plan_windows(day, day, 91, direction),
+                [window("2024-01-01", "2024-01-01")]
+            );
+        }
+    }
+
+    #[test]
+    fn reversed_dates_are_rejected() {
+        let e = BackfillRange::new(
+            Dataset::AirQuality,
+            Some(date("2024-02-01")),
+            Some(date("2024-01-01")),
+            DEFAULT_WINDOW_DAYS,
+            Direction::Backward,
+        )
+        .unwrap_err();
+        assert!(matches!(e, Error::InvalidRange(_)), "{}", e);
+    }
+
+    #[test]
+    fn dates_outside_the_archive_are_rejected() {
+        let before_air_quality = BackfillRange::new(
+            Dataset::AirQuality,
+            Some(date("2022-07-31")),
+            Some(date("2023-01-01")),
+            DEFAULT_WINDOW_DAYS,
+            Direction::Backward,
+        );
+        assert!(matches!(before_air_quality, Err(Error::InvalidRange(_))));
+
+        let future = Utc::now().date_naive() + TimeDuration::days(1);
+        let in_future = BackfillRange::new(
+            Dataset::Weather,
+            Some(date("2024-01-01")),
+            Some(future),
+            DEFAULT_WINDOW_DAYS,
+            Direction::Backward,
+        );
+        assert!(matches!(in_future, Err(Error::InvalidRange(_))));
+    }
+
+    #[test]
+    fn weather_reaches_further_back_than_air_quality() {
+        let range = BackfillRange::new(
+            Dataset::Weather,
+            Some(date("1990-01-01")),
+            Some(date("1990-12-31")),
+            DEFAULT_WINDOW_DAYS,
+            Direction::Forward,
+        )
+        .unwrap();
+        assert_eq!(range.start, date("1990-01-01"));
+
+        let too_early = BackfillRange::new(
+            Dataset::Weather,
+            Some(date("1939-12-31")),
+            None,
+            DEFAULT_WINDOW_DAYS,
+            Direction::Forward,
+        );
+        assert!(matches!(too_early, Err(Error::InvalidRange(_))));
+    }
+
+    #[test]
+    fn window_size_is_bounded_by_the_api() {
+        for window_days in [0, API_MAX_WINDOW_DAYS + 1] {
+            let range = BackfillRange::new(
+                Dataset::AirQuality,
+                None,
+                None,
+                window_days,
+                Direction::Backward,
+            );
+            assert!(matches!(range, Err(Error::InvalidRange(_))));
+        }
+    }
+}
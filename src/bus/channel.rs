@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::error::Result;
+use crate::traits::message_bus::{BusMessage, MessagePublisher, MessageSubscriber};
+
+/// An in-process bus holding at most `capacity` messages. Commits are no-ops.
+pub fn channel(capacity: usize) -> (ChannelPublisher, ChannelSubscriber) {
+    let (tx, rx) = mpsc::channel(capacity);
+    (
+        ChannelPublisher {
+            tx,
+            next_offset: Arc::new(AtomicI64::new(0)),
+        },
+        ChannelSubscriber { rx },
+    )
+}
+
+#[derive(Clone)]
+pub struct ChannelPublisher {
+    tx: mpsc::Sender<BusMessage>,
+    next_offset: Arc<AtomicI64>,
+}
+
+#[async_trait]
+impl MessagePublisher for ChannelPublisher {
+    async fn publish(
+        &self,
+        topic: &str,
+        key: Option<&str>,
+        payload: &[u8],
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let msg = BusMessage {
+            topic: topic.to_string(),
+            partition: 0,
+            offset: self.next_offset.fetch_add(1, Ordering::Relaxed),
+            key: key.map(str::to_string),
+            payload: Some(payload.to_vec()),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        // The subscriber only goes away once it is done consuming, at which
+        // point there is nobody left to deliver to.
+        let _ = self.tx.send(msg).await;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Receives every topic, until all publishers have been dropped.
+pub struct ChannelSubscriber {
+    rx: mpsc::Receiver<BusMessage>,
+}
+
+#[async_trait]
+impl MessageSubscriber for ChannelSubscriber {
+    async fn next(&mut self) -> Option<Result<BusMessage>> {
+        self.rx.recv().await.map(Ok)
+    }
+
+    async fn commit(&mut self, _msg: &BusMessage) -> Result<()> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
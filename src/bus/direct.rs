@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::marker::PhantomData;
+
+use crate::config::ConsumerConfig;
+use crate::db;
+use crate::error::Result;
+use crate::kafka::consumer::save_with_retry;
+use crate::shutdown::Shutdown;
+use crate::traits::data_fetcher::HourlyRecord;
+use crate::traits::data_loader::{ConflictStrategy, Persistable};
+use crate::traits::message_bus::MessagePublisher;
+
+/// Writes every published batch of `R`s straight to the database, with the
+/// consumer's retries and conflict handling.
+pub struct DirectPublisher<R> {
+    pool: PgPool,
+    on_conflict: ConflictStrategy,
+    retry: ConsumerConfig,
+    shutdown: Shutdown,
+    record: PhantomData<fn() -> R>,
+}
+
+impl<R> DirectPublisher<R> {
+    pub async fn connect(
+        db_url: &str,
+        on_conflict: ConflictStrategy,
+        retry: ConsumerConfig,
+        shutdown: Shutdown,
+    ) -> Result<Self> {
+        Ok(DirectPublisher {
+            pool: db::connect(db_url).await?,
+            on_conflict,
+            retry,
+            shutdown,
+            record: PhantomData,
+        })
+    }
+
+    pub async fn close(self) {
+        self.pool.close().await;
+    }
+}
+
+#[async_trait]
+impl<R> MessagePublisher for DirectPublisher<R>
+where
+    R: HourlyRecord,
+    Vec<R>: Persistable,
+{
+    async fn publish(
+        &self,
+        _topic: &str,
+        _key: Option<&str>,
+        payload: &[u8],
+        _headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let batch: Vec<R> = serde_json::from_slice(payload)?;
+        save_with_retry(
+            &batch,
+            &self.pool,
+            self.on_conflict,
+            &self.retry,
+            &self.shutdown,
+        )
+        .await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LocationConfig, ScheduleConfig};
+    use crate::error::Error;
+    use crate::kafka::producer::run_recent_producer;
+    use crate::schedule::Schedule;
+    use crate::testing::{berlin, quick_retry, reading, unused_pool, Reading};
+    use crate::traits::data_fetcher::DataFetcher;
+    use chrono::{DateTime, Duration as TimeDuration, DurationRound, Utc};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn publisher() -> DirectPublisher<Reading> {
+        DirectPublisher {
+            pool: unused_pool(),
+            on_conflict: ConflictStrategy::Skip,
+            retry: quick_retry(),
+            shutdown: Shutdown::never(),
+            record: PhantomData,
+        }
+    }
+
+    async fn publish(publisher: &DirectPublisher<Reading>, batch: &[Reading]) -> Result<()> {
+        let payload = serde_json::to_vec(batch).unwrap();
+        publisher
+            .publish("air-quality", Some("berlin"), &payload, &[])
+            .await
+    }
+
+    /// Serves readings the database rejects, for the current hour and for
+    /// every hour asked for, and runs out after two recent fetches.
+    struct Rejected {
+        location: LocationConfig,
+        recent: AtomicUsize,
+        historical: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DataFetcher for Rejected {
+        type Record = Reading;
+
+        fn location(&self) -> &LocationConfig {
+            &self.location
+        }
+
+        async fn fetch_historical(
+            &self,
+            start_date: &str,
+            _end_date: &str,
+        ) -> Result<Vec<Reading>> {
+            self.historical.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![reading(
+                "berlin",
+                &format!("{}T00:00:00Z", start_date),
+                -1.0,
+            )])
+        }
+
+        async fn fetch_recent(&self) -> Result<Vec<Reading>> {
+            if self.recent.fetch_add(1, Ordering::SeqCst) == 2 {
+                return Err(Error::RecordingExhausted {
+                    location: "berlin".to_string(),
+                });
+            }
+            Ok(vec![Reading {
+                time: this_hour(),
+                ..reading("berlin", "2024-01-01T00:00:00Z", -1.0)
+            }])
+        }
+    }
+
+    fn this_hour() -> DateTime<Utc> {
+        Utc::now().duration_trunc(TimeDuration::hours(1)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn published_batches_are_saved() {
+        let batch = [reading("berlin", "2024-03-01T00:00:00Z", 12.0)];
+        publish(&publisher(), &batch).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_batches_fail_for_good() {
+        let publisher = publisher();
+
+        let batch = [reading("berlin", "2024-03-01T00:00:00Z", -1.0)];
+        let e = publish(&publisher, &batch).await.unwrap_err();
+        assert!(matches!(e, Error::Database(_)));
+        assert!(!e.is_retryable());
+
+        let e = publisher
+            .publish("air-quality", Some("berlin"), b"not a batch", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(e, Error::Deserialize(_)));
+        assert!(!e.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn rejected_batches_are_not_fetched_again() {
+        let fetcher = Rejected {
+            location: berlin(),
+            recent: AtomicUsize::new(0),
+            historical: AtomicUsize::new(0),
+        };
+        let last_ingested =
+            HashMap::from([("berlin".to_string(), this_hour() - TimeDuration::hours(3))]);
+        let schedule = Schedule::from_config(&ScheduleConfig {
+            offset_minutes: 0,
+            cron: Some("* * * * * *".to_string()),
+        })
+        .unwrap();
+
+        run_recent_producer(
+            &publisher(),
+            "air-quality",
+            std::slice::from_ref(&fetcher),
+            &schedule,
+            &last_ingested,
+            &Shutdown::never(),
+        )
+        .await
+        .unwrap();
+
+        // Only the first fetch found a gap to catch up on
+        assert_eq!(fetcher.recent.load(Ordering::SeqCst), 3);
+        assert_eq!(fetcher.historical.load(Ordering::SeqCst), 1);
+    }
+}
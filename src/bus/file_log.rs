@@ -0,0 +1,249 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::error::Result;
+use crate::traits::message_bus::{BusMessage, MessagePublisher, MessageSubscriber};
+
+/// How often a subscriber that reached the end of a log looks for new lines.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One line of `<dir>/<topic>.log`, with the payload written as text.
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    offset: i64,
+    key: Option<String>,
+    payload: Option<String>,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    timestamp: DateTime<Utc>,
+}
+
+fn log_path(dir: &Path, topic: &str) -> PathBuf {
+    dir.join(format!("{}.log", topic))
+}
+
+/// Offsets are line numbers, so only one process may publish to a topic.
+pub struct FileLogPublisher {
+    dir: PathBuf,
+    logs: Mutex<HashMap<String, (File, i64)>>,
+}
+
+impl FileLogPublisher {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileLogPublisher {
+            dir: dir.into(),
+            logs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Also returns the next offset, after cutting off a torn last line.
+    fn open(&self, topic: &str) -> io::Result<(File, i64)> {
+        fs::create_dir_all(&self.dir)?;
+        let path = log_path(&self.dir, topic);
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut reader = BufReader::new(&file);
+        let mut line = Vec::new();
+        let mut next_offset = 0;
+        let mut complete_len = 0;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            next_offset += 1;
+            complete_len += read as u64;
+        }
+        if file.metadata()?.len() > complete_len {
+            file.set_len(complete_len)?;
+        }
+        Ok((file, next_offset))
+    }
+}
+
+#[async_trait]
+impl MessagePublisher for FileLogPublisher {
+    async fn publish(
+        &self,
+        topic: &str,
+        key: Option<&str>,
+        payload: &[u8],
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let mut logs = self.logs.lock().await;
+        if !logs.contains_key(topic) {
+            let log = self.open(topic)?;
+            logs.insert(topic.to_string(), log);
+        }
+        let (file, next_offset) = logs.get_mut(topic).expect("log opened above");
+
+        let entry = LogEntry {
+            offset: *next_offset,
+            key: key.map(str::to_string),
+            payload: Some(String::from_utf8_lossy(payload).into_owned()),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            timestamp: Utc::now(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        *next_offset += 1;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        for (file, _) in self.logs.lock().await.values() {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows a topic's log from the offset in `<dir>/<topic>.<group>.offset`.
+pub struct FileLogSubscriber {
+    dir: PathBuf,
+    topic: String,
+    group: String,
+    /// Entries before this offset were committed by an earlier run.
+    start: i64,
+    reader: Option<BufReader<File>>,
+    /// Part of a line whose writer has not finished it yet.
+    line: String,
+}
+
+impl FileLogSubscriber {
+    pub fn new(dir: impl Into<PathBuf>, group: &str, topic: &str) -> Result<Self> {
+        let mut subscriber = FileLogSubscriber {
+            dir: dir.into(),
+            topic: topic.to_string(),
+            group: group.to_string(),
+            start: 0,
+            reader: None,
+            line: String::new(),
+        };
+        subscriber.start = match fs::read_to_string(subscriber.offset_path()) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(subscriber)
+    }
+
+    fn offset_path(&self) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}.offset", self.topic, self.group))
+    }
+
+    /// Synchronous, so a cancelled `next` never drops half a line.
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if self.reader.is_none() {
+            match File::open(log_path(&self.dir, &self.topic)) {
+                Ok(file) => self.reader = Some(BufReader::new(file)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        let reader = self.reader.as_mut().expect("reader opened above");
+        reader.read_line(&mut self.line)?;
+        if !self.line.ends_with('\n') {
+            return Ok(None);
+        }
+        Ok(Some(std::mem::take(&mut self.line)))
+    }
+}
+
+#[async_trait]
+impl MessageSubscriber for FileLogSubscriber {
+    async fn next(&mut self) -> Option<Result<BusMessage>> {
+        loop {
+            let line = match self.read_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(e) => return Some(Err(e.into())),
+            };
+            let entry: LogEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if entry.offset < self.start {
+                continue;
+            }
+            return Some(Ok(BusMessage {
+                topic: self.topic.clone(),
+                partition: 0,
+                offset: entry.offset,
+                key: entry.key,
+                payload: entry.payload.map(String::into_bytes),
+                headers: entry.headers,
+            }));
+        }
+    }
+
+    /// Written through a temporary file so a crash never leaves a torn offset.
+    async fn commit(&mut self, msg: &BusMessage) -> Result<()> {
+        let path = self.offset_path();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, (msg.offset + 1).to_string())?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[tokio::test]
+    async fn torn_line_is_cut_off_on_open() {
+        let dir = temp_dir("file_log_torn");
+        let publisher = FileLogPublisher::new(&dir);
+        publisher.publish("t", None, b"first", &[]).await.unwrap();
+        drop(publisher);
+
+        // A crash halfway through the second entry
+        let path = log_path(&dir, "t");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"offset":1,"key":nu"#).unwrap();
+        drop(file);
+
+        let publisher = FileLogPublisher::new(&dir);
+        publisher.publish("t", None, b"second", &[]).await.unwrap();
+        drop(publisher);
+
+        let mut subscriber = FileLogSubscriber::new(&dir, "g", "t").unwrap();
+        let first = subscriber.next().await.unwrap().unwrap();
+        let second = subscriber.next().await.unwrap().unwrap();
+        assert_eq!(first.offset, 0);
+        assert_eq!(second.offset, 1);
+        assert_eq!(second.payload.as_deref(), Some(&b"second"[..]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{Offset, TopicPartitionList};
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::traits::message_bus::{BusMessage, MessagePublisher, MessageSubscriber};
+
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    flush_timeout: Duration,
+}
+
+impl KafkaPublisher {
+    /// `flush_timeout` should fit in the shutdown deadline.
+    pub fn new(broker: &str, flush_timeout: Duration) -> Result<Self> {
+        Ok(KafkaPublisher {
+            producer: ClientConfig::new()
+                .set("bootstrap.servers", broker)
+                .create()?,
+            flush_timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl MessagePublisher for KafkaPublisher {
+    async fn publish(
+        &self,
+        topic: &str,
+        key: Option<&str>,
+        payload: &[u8],
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let headers = headers
+            .iter()
+            .fold(OwnedHeaders::new(), |acc, (key, value)| {
+                acc.insert(Header {
+                    key,
+                    value: Some(*value),
+                })
+            });
+        let mut record: FutureRecord<'_, str, [u8]> =
+            FutureRecord::to(topic).payload(payload).headers(headers);
+        if let Some(key) = key {
+            record = record.key(key);
+        }
+
+        self.producer
+            .send(record, Duration::from_secs(0))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| e.into())
+    }
+
+    /// librdkafka's flush blocks, so it runs on the blocking pool.
+    async fn flush(&self) -> Result<()> {
+        let (producer, timeout) = (self.producer.clone(), self.flush_timeout);
+        tokio::task::spawn_blocking(move || producer.flush(timeout))
+            .await
+            .map_err(io::Error::from)??;
+        Ok(())
+    }
+}
+
+/// Auto-commit is off, offsets only move through [`MessageSubscriber::commit`].
+pub struct KafkaSubscriber {
+    consumer: StreamConsumer,
+    committed: HashMap<(String, i32), i64>,
+}
+
+impl KafkaSubscriber {
+    pub fn new(broker: &str, group_id: &str, topics: &[&str]) -> Result<Self> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", broker)
+            .set("group.id", group_id)
+            .set("auto.offset.reset", "earliest")
+            .set("enable.auto.commit", "false")
+            .create()?;
+        consumer.subscribe(topics)?;
+
+        Ok(KafkaSubscriber {
+            consumer,
+            committed: HashMap::new(),
+        })
+    }
+}
+
+fn to_bus_message(msg: &BorrowedMessage<'_>) -> BusMessage {
+    let headers = msg
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|header| {
+                    let value = header
+                        .value
+                        .map(String::from_utf8_lossy)
+                        .unwrap_or_default();
+                    (header.key.to_string(), value.into_owned())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    BusMessage {
+        topic: msg.topic().to_string(),
+        partition: msg.partition(),
+        offset: msg.offset(),
+        key: msg
+            .key()
+            .map(|key| String::from_utf8_lossy(key).into_owned()),
+        payload: msg.payload().map(<[u8]>::to_vec),
+        headers,
+    }
+}
+
+#[async_trait]
+impl MessageSubscriber for KafkaSubscriber {
+    async fn next(&mut self) -> Option<Result<BusMessage>> {
+        Some(
+            self.consumer
+                .recv()
+                .await
+                .map(|msg| to_bus_message(&msg))
+                .map_err(Into::into),
+        )
+    }
+
+    async fn commit(&mut self, msg: &BusMessage) -> Result<()> {
+        let mut offsets = TopicPartitionList::new();
+        // Kafka stores the offset of the next message to read
+        offsets.add_partition_offset(&msg.topic, msg.partition, Offset::Offset(msg.offset + 1))?;
+        self.consumer.commit(&offsets, CommitMode::Async)?;
+        self.committed
+            .insert((msg.topic.clone(), msg.partition), msg.offset + 1);
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if self.committed.is_empty() {
+            return Ok(());
+        }
+        let mut offsets = TopicPartitionList::new();
+        for ((topic, partition), offset) in &self.committed {
+            offsets.add_partition_offset(topic, *partition, Offset::Offset(*offset))?;
+        }
+        self.consumer.commit(&offsets, CommitMode::Sync)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+pub mod channel;
+pub mod direct;
+pub mod file_log;
+pub mod kafka;
+
+pub use channel::channel;
+pub use direct::DirectPublisher;
+pub use file_log::{FileLogPublisher, FileLogSubscriber};
+pub use kafka::{KafkaPublisher, KafkaSubscriber};
@@ -0,0 +1,232 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::backfill::{plan_windows, BackfillRange, DateWindow, Direction};
+
+/// Stores the plan with its completed windows, so a restart plans the same
+/// windows even when the end date was left open.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocationCheckpoint {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub window_days: i64,
+    pub direction: Direction,
+    pub completed: Vec<DateWindow>,
+}
+
+impl LocationCheckpoint {
+    fn matches(&self, range: &BackfillRange) -> bool {
+        self.start == range.start
+            && range.end.is_none_or(|end| end == self.end)
+            && self.window_days == range.window_days
+            && self.direction == range.direction
+    }
+
+    /// Windows of the plan that have not been published yet, in fetch order.
+    pub fn pending(&self) -> Vec<DateWindow> {
+        plan_windows(self.start, self.end, self.window_days, self.direction)
+            .into_iter()
+            .filter(|window| !self.completed.contains(window))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CheckpointFile {
+    locations: HashMap<String, LocationCheckpoint>,
+}
+
+/// Published historical windows, saved as JSON so a backfill can resume.
+pub struct CheckpointStore {
+    /// `None` keeps progress in memory only, so every run starts over
+    path: Option<PathBuf>,
+    state: Mutex<CheckpointFile>,
+}
+
+impl CheckpointStore {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let state = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => CheckpointFile::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(CheckpointStore {
+            path: Some(path.to_path_buf()),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// A store that forgets everything when the process exits.
+    pub fn in_memory() -> Self {
+        CheckpointStore {
+            path: None,
+            state: Mutex::new(CheckpointFile::default()),
+        }
+    }
+
+    pub fn reset(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts over when the stored checkpoint was recorded for another range.
+    pub async fn start(
+        &self,
+        location: &str,
+        range: &BackfillRange,
+        today: NaiveDate,
+    ) -> io::Result<LocationCheckpoint> {
+        let mut state = self.state.lock().await;
+        if let Some(checkpoint) = state.locations.get(location) {
+            if checkpoint.matches(range) {
+                return Ok(checkpoint.clone());
+            }
+            warn!(target: "producer",
+                "[Producer] Checkpoint for {} was recorded for a different range, starting over",
+                location
+            );
+        }
+
+        let checkpoint = LocationCheckpoint {
+            start: range.start,
+            end: range.end.unwrap_or(today),
+            window_days: range.window_days,
+            direction: range.direction,
+            completed: Vec::new(),
+        };
+        state
+            .locations
+            .insert(location.to_string(), checkpoint.clone());
+        self.write(&state).await?;
+        Ok(checkpoint)
+    }
+
+    pub async fn mark_complete(&self, location: &str, window: DateWindow) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(checkpoint) = state.locations.get_mut(location) {
+            if !checkpoint.completed.contains(&window) {
+                checkpoint.completed.push(window);
+            }
+        }
+        self.write(&state).await
+    }
+
+    /// Renamed into place, so a crash never leaves a truncated file.
+    async fn write(&self, state: &CheckpointFile) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let contents = serde_json::to_string_pretty(state)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(&tmp_path, path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Dataset;
+    use crate::testing::{date, temp_dir};
+
+    fn range(end: Option<&str>) -> BackfillRange {
+        BackfillRange::new(
+            Dataset::AirQuality,
+            Some(date("2024-01-01")),
+            end.map(date),
+            3,
+            Direction::Forward,
+        )
+        .unwrap()
+    }
+
+    fn store(name: &str) -> (CheckpointStore, PathBuf) {
+        let path = temp_dir(&format!("checkpoint_{}", name)).join("historical.json");
+        (CheckpointStore::load(&path).unwrap(), path)
+    }
+
+    #[test]
+    fn pending_skips_completed_windows() {
+        let checkpoint = LocationCheckpoint {
+            start: date("2024-01-01"),
+            end: date("2024-01-08"),
+            window_days: 3,
+            direction: Direction::Forward,
+            completed: vec![DateWindow {
+                start: date("2024-01-04"),
+                end: date("2024-01-06"),
+            }],
+        };
+        assert_eq!(
+            checkpoint.pending(),
+            [
+                DateWindow {
+                    start: date("2024-01-01"),
+                    end: date("2024-01-03"),
+                },
+                DateWindow {
+                    start: date("2024-01-07"),
+                    end: date("2024-01-08"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn an_open_ended_backfill_resumes_the_same_plan_on_a_later_day() {
+        let (store, path) = store("resume");
+        let checkpoint = store
+            .start("Berlin", &range(None), date("2024-01-08"))
+            .await
+            .unwrap();
+        let first = checkpoint.pending()[0];
+        store.mark_complete("Berlin", first).await.unwrap();
+
+        let store = CheckpointStore::load(&path).unwrap();
+        let resumed = store
+            .start("Berlin", &range(None), date("2024-03-01"))
+            .await
+            .unwrap();
+        assert_eq!(resumed.end, date("2024-01-08"));
+        assert_eq!(resumed.completed, [first]);
+        assert_eq!(resumed.pending().len(), checkpoint.pending().len() - 1);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_different_range_starts_over() {
+        let (store, path) = store("restart");
+        let checkpoint = store
+            .start("Berlin", &range(Some("2024-01-08")), date("2024-01-08"))
+            .await
+            .unwrap();
+        store
+            .mark_complete("Berlin", checkpoint.pending()[0])
+            .await
+            .unwrap();
+
+        let restarted = store
+            .start("Berlin", &range(Some("2024-01-05")), date("2024-01-08"))
+            .await
+            .unwrap();
+        assert!(restarted.completed.is_empty());
+        assert_eq!(restarted.pending().len(), 2);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,26 +1,339 @@
+use clap::ValueEnum;
 use config::Config;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+use crate::air_models::air_model::WELL_KNOWN_VARIABLES;
+use crate::traits::data_loader::ConflictStrategy;
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct LocationConfig {
+    pub name: String,
     pub latitude: f64,
     pub longitude: f64,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DBConfig {
     pub db_url: String,
+    /// How rows that already exist for a location and hour are handled
+    #[serde(default)]
+    pub on_conflict: ConflictStrategy,
+    /// Refuse to write to a database that has migrations pending
+    #[serde(default)]
+    pub require_current_schema: bool,
+    /// Time span covered by each hypertable chunk, as a Postgres interval
+    #[serde(default = "default_chunk_interval")]
+    pub chunk_interval: String,
+    /// Compress chunks, segmented by location
+    #[serde(default = "default_compression")]
+    pub compression: bool,
+    /// Age after which chunks are compressed
+    #[serde(default = "default_compress_after")]
+    pub compress_after: String,
+    /// Age after which chunks are dropped, kept forever when unset
+    #[serde(default)]
+    pub retention: Option<String>,
+}
+
+fn default_chunk_interval() -> String {
+    "7 days".to_string()
+}
+
+fn default_compression() -> bool {
+    true
+}
+
+fn default_compress_after() -> String {
+    "30 days".to_string()
+}
+
+/// CAMS models the air quality API may answer from.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Domains {
+    /// Europe where available, global elsewhere
+    #[default]
+    Auto,
+    CamsEurope,
+    CamsGlobal,
+}
+
+impl Domains {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Domains::Auto => "auto",
+            Domains::CamsEurope => "cams_europe",
+            Domains::CamsGlobal => "cams_global",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Retries after the first attempt of a request before giving up
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub backoff_base_ms: u64,
+    /// Upper bound for the delay between retries
+    pub backoff_max_ms: u64,
+    /// Open-Meteo hourly variables to request
+    pub hourly: Vec<String>,
+    /// Days ahead requested by the forecast producer
+    pub forecast_days: u32,
+    /// Key for Open-Meteo's commercial API, sent with every request
+    pub apikey: Option<String>,
+    /// Air quality API host, e.g. a self-hosted instance or a mock server
+    pub base_url: Option<String>,
+    /// Weather forecast API host
+    pub weather_base_url: Option<String>,
+    /// Weather archive API host
+    pub archive_base_url: Option<String>,
+    /// Seconds before a request is abandoned and retried
+    pub timeout_secs: u64,
+    pub user_agent: String,
+    /// Proxy for all requests, e.g. `http://proxy:3128`
+    pub proxy: Option<String>,
+    /// CAMS models the air quality API answers from
+    pub domains: Domains,
+}
+
+impl ApiConfig {
+    /// Keyed requests go to the `customer-` counterpart of `host`.
+    fn host(&self, configured: &Option<String>, host: &str) -> String {
+        match configured {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None if self.apikey.is_some() => format!("https://customer-{}", host),
+            None => format!("https://{}", host),
+        }
+    }
+
+    pub fn air_quality_url(&self) -> String {
+        format!(
+            "{}/v1/air-quality",
+            self.host(&self.base_url, "air-quality-api.open-meteo.com")
+        )
+    }
+
+    pub fn forecast_url(&self) -> String {
+        format!(
+            "{}/v1/forecast",
+            self.host(&self.weather_base_url, "api.open-meteo.com")
+        )
+    }
+
+    pub fn archive_url(&self) -> String {
+        format!(
+            "{}/v1/archive",
+            self.host(&self.archive_base_url, "archive-api.open-meteo.com")
+        )
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            max_retries: 4,
+            backoff_base_ms: 1_000,
+            backoff_max_ms: 60_000,
+            hourly: WELL_KNOWN_VARIABLES.iter().map(|v| v.to_string()).collect(),
+            forecast_days: 5,
+            apikey: None,
+            base_url: None,
+            weather_base_url: None,
+            archive_base_url: None,
+            timeout_secs: 30,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            proxy: None,
+            domains: Domains::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// Minutes past each wall-clock hour at which the recent producer fetches
+    pub offset_minutes: u32,
+    /// Cron expression (with seconds) used instead of `offset_minutes`
+    pub cron: Option<String>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            offset_minutes: 5,
+            cron: None,
+        }
+    }
+}
+
+/// Each data set has its own topics and table.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    AirQuality,
+    Weather,
+    /// Air quality forecasts, tagged with when they were issued
+    Forecast,
+}
+
+impl Dataset {
+    /// Table the consumer writes this dataset to
+    pub fn table(&self) -> &'static str {
+        match self {
+            Dataset::AirQuality => "air_quality",
+            Dataset::Weather => "weather",
+            Dataset::Forecast => "air_quality_forecast",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KafkaConfig {
+    pub topic: String,
+    /// Topic receiving messages the consumer could not parse or store
+    pub dlq_topic: String,
+    pub group_id: String,
+    /// Topic carrying weather readings
+    pub weather_topic: String,
+    /// Dead-letter topic for weather readings
+    pub weather_dlq_topic: String,
+    /// Topic carrying air quality forecasts
+    pub forecast_topic: String,
+    /// Dead-letter topic for forecasts
+    pub forecast_dlq_topic: String,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        KafkaConfig {
+            topic: "weather-data".to_string(),
+            dlq_topic: "weather-data-dlq".to_string(),
+            group_id: "hello-group".to_string(),
+            weather_topic: "weather-observations".to_string(),
+            weather_dlq_topic: "weather-observations-dlq".to_string(),
+            forecast_topic: "air-quality-forecast".to_string(),
+            forecast_dlq_topic: "air-quality-forecast-dlq".to_string(),
+        }
+    }
+}
+
+impl KafkaConfig {
+    pub fn topic_for(&self, dataset: Dataset) -> &str {
+        match dataset {
+            Dataset::AirQuality => &self.topic,
+            Dataset::Weather => &self.weather_topic,
+            Dataset::Forecast => &self.forecast_topic,
+        }
+    }
+
+    pub fn dlq_topic_for(&self, dataset: Dataset) -> &str {
+        match dataset {
+            Dataset::AirQuality => &self.dlq_topic,
+            Dataset::Weather => &self.weather_dlq_topic,
+            Dataset::Forecast => &self.forecast_dlq_topic,
+        }
+    }
+
+    /// Every data set other than air quality gets a group of its own.
+    pub fn group_id_for(&self, dataset: Dataset) -> String {
+        match dataset {
+            Dataset::AirQuality => self.group_id.clone(),
+            Dataset::Weather => format!("{}-weather", self.group_id),
+            Dataset::Forecast => format!("{}-forecast", self.group_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConsumerConfig {
+    /// Attempts at writing a batch before the consumer gives up and stops
+    pub db_max_retries: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub db_retry_base_ms: u64,
+    /// Upper bound for the delay between retries
+    pub db_retry_max_ms: u64,
+}
+
+impl Default for ConsumerConfig {
+    fn default() -> Self {
+        ConsumerConfig {
+            db_max_retries: 5,
+            db_retry_base_ms: 500,
+            db_retry_max_ms: 30_000,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
-    pub location: LocationConfig,
+    pub locations: Vec<LocationConfig>,
     pub database: DBConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub kafka: KafkaConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub consumer: ConsumerConfig,
 }
 
 pub fn load_config() -> Result<AppConfig, config::ConfigError> {
-    Config::builder()
+    let config: AppConfig = Config::builder()
         .add_source(config::File::with_name("config"))
         .build()?
-        .try_deserialize()
+        .try_deserialize()?;
+
+    if config.locations.is_empty() {
+        return Err(config::ConfigError::Message(
+            "at least one [[locations]] entry is required".to_string(),
+        ));
+    }
+
+    if config.api.hourly.is_empty() {
+        return Err(config::ConfigError::Message(
+            "[api] hourly needs at least one variable".to_string(),
+        ));
+    }
+    if let Some(variable) = config
+        .api
+        .hourly
+        .iter()
+        .find(|v| v.is_empty() || !v.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+    {
+        return Err(config::ConfigError::Message(format!(
+            "invalid hourly variable '{}'",
+            variable
+        )));
+    }
+
+    // The air quality API forecasts up to a week ahead
+    if !(1..=7).contains(&config.api.forecast_days) {
+        return Err(config::ConfigError::Message(format!(
+            "[api] forecast_days must be between 1 and 7, got {}",
+            config.api.forecast_days
+        )));
+    }
+
+    if config.api.timeout_secs == 0 {
+        return Err(config::ConfigError::Message(
+            "[api] timeout_secs must be at least 1".to_string(),
+        ));
+    }
+
+    let mut names = std::collections::HashSet::new();
+    for location in &config.locations {
+        if !names.insert(location.name.as_str()) {
+            return Err(config::ConfigError::Message(format!(
+                "duplicate location name '{}'",
+                location.name
+            )));
+        }
+    }
+    Ok(config)
 }
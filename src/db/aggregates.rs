@@ -0,0 +1,67 @@
+use chrono::NaiveDate;
+use sqlx::{Executor, PgPool};
+use tracing::info;
+
+use crate::error::Result;
+
+/// Continuous aggregates defined by the migrations, in refresh order.
+pub const CONTINUOUS_AGGREGATES: [&str; 2] =
+    ["air_quality_daily_stats", "air_quality_weekly_stats"];
+
+/// Timescale only refreshes the last few buckets on its own.
+pub async fn refresh_aggregates(
+    pool: &PgPool,
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+) -> Result<()> {
+    for aggregate in CONTINUOUS_AGGREGATES {
+        info!("Refreshing {}", aggregate);
+        // Sent as a plain string, which sqlx runs over the simple query
+        // protocol: the refresh refuses to run inside a transaction block.
+        pool.execute(refresh_statement(aggregate, start, end).as_str())
+            .await?;
+    }
+    Ok(())
+}
+
+/// The bounds are formatted from dates, so they are always valid literals.
+fn refresh_statement(aggregate: &str, start: Option<NaiveDate>, end: Option<NaiveDate>) -> String {
+    let bound = |date: Option<NaiveDate>| match date {
+        Some(date) => format!("'{}T00:00:00Z'::timestamptz", date.format("%Y-%m-%d")),
+        None => "NULL::timestamptz".to_string(),
+    };
+    format!(
+        "CALL refresh_continuous_aggregate('{}', {}, {})",
+        aggregate,
+        bound(start),
+        bound(end)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    #[test]
+    fn refresh_bounds_are_utc_midnights() {
+        assert_eq!(
+            refresh_statement(
+                "air_quality_daily_stats",
+                Some(date("2024-03-01")),
+                Some(date("2024-03-08"))
+            ),
+            "CALL refresh_continuous_aggregate('air_quality_daily_stats', \
+             '2024-03-01T00:00:00Z'::timestamptz, '2024-03-08T00:00:00Z'::timestamptz)"
+        );
+    }
+
+    #[test]
+    fn missing_refresh_bounds_leave_the_window_open() {
+        assert_eq!(
+            refresh_statement("air_quality_weekly_stats", None, Some(date("2024-03-08"))),
+            "CALL refresh_continuous_aggregate('air_quality_weekly_stats', \
+             NULL::timestamptz, '2024-03-08T00:00:00Z'::timestamptz)"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+pub mod aggregates;
+pub mod timescale;
+pub mod verification;
+
+use chrono::{DateTime, Utc};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// Schema migrations from `migrations/`, embedded at build time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but the file has been edited since.
+    Changed,
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+pub async fn connect(db_url: &str) -> Result<PgPool> {
+    Ok(PgPoolOptions::new()
+        .max_connections(10)
+        .acquire_timeout(Duration::from_secs(20))
+        .connect(db_url)
+        .await?)
+}
+
+/// Locations without any rows in `table` are left out.
+pub async fn last_ingested(
+    pool: &PgPool,
+    table: &str,
+    locations: &[String],
+) -> Result<HashMap<String, DateTime<Utc>>> {
+    let rows: Vec<(String, DateTime<Utc>)> = sqlx::query_as(&format!(
+        "SELECT location, MAX(_time) FROM {} WHERE location = ANY($1) GROUP BY location",
+        table
+    ))
+    .bind(locations)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Applies every pending migration.
+pub async fn migrate(pool: &PgPool) -> Result<()> {
+    Ok(MIGRATOR.run(pool).await?)
+}
+
+/// Does not create the migrations table on a database never migrated.
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>> {
+    let (tracked,): (bool,) = sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    let applied = if tracked {
+        let mut conn = pool.acquire().await?;
+        conn.list_applied_migrations().await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| {
+            let state = match applied.iter().find(|a| a.version == migration.version) {
+                Some(a) if a.checksum == migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::Changed,
+                None => MigrationState::Pending,
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state,
+            }
+        })
+        .collect())
+}
+
+/// Fails with [`Error::SchemaBehind`] when migrations are pending.
+pub async fn ensure_schema_current(pool: &PgPool) -> Result<()> {
+    let pending = migration_status(pool)
+        .await?
+        .iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .count();
+    if pending > 0 {
+        return Err(Error::SchemaBehind { pending });
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::config::DBConfig;
+use crate::error::Result;
+
+/// Every column of the table's unique key must be a segment by or an order
+/// by column for compression.
+pub struct Hypertable {
+    pub name: &'static str,
+    pub segment_by: &'static str,
+    pub order_by: &'static str,
+}
+
+pub const HYPERTABLES: [Hypertable; 3] = [
+    Hypertable {
+        name: "air_quality",
+        segment_by: "location",
+        order_by: "_time DESC",
+    },
+    Hypertable {
+        name: "weather",
+        segment_by: "location",
+        order_by: "_time DESC",
+    },
+    Hypertable {
+        name: "air_quality_forecast",
+        segment_by: "location",
+        order_by: "issue_time, _time DESC",
+    },
+];
+
+/// Timescale settings of one hypertable as stored in the database.
+pub struct Policies {
+    /// `None` when the table is not a hypertable yet.
+    pub chunk_interval: Option<String>,
+    pub compression_enabled: bool,
+    pub segment_by: Vec<String>,
+    pub order_by: Vec<String>,
+    pub compress_after: Option<String>,
+    pub retention: Option<String>,
+}
+
+/// Compression settings are left alone once enabled, since Timescale refuses
+/// to change them while compressed chunks exist.
+pub async fn apply_policies(pool: &PgPool, config: &DBConfig) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for hypertable in &HYPERTABLES {
+        apply_table_policies(&mut tx, hypertable, config).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn apply_table_policies(
+    tx: &mut Transaction<'_, Postgres>,
+    hypertable: &Hypertable,
+    config: &DBConfig,
+) -> Result<()> {
+    let table = hypertable.name;
+    sqlx::query("SELECT set_chunk_time_interval($1::regclass, $2::interval)")
+        .bind(table)
+        .bind(&config.chunk_interval)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query("SELECT remove_compression_policy($1::regclass, if_exists => true)")
+        .bind(table)
+        .execute(&mut **tx)
+        .await?;
+    if config.compression {
+        let (enabled,): (bool,) = sqlx::query_as(
+            "SELECT compression_enabled FROM timescaledb_information.hypertables \
+             WHERE hypertable_name = $1",
+        )
+        .bind(table)
+        .fetch_one(&mut **tx)
+        .await?;
+        if !enabled {
+            sqlx::query(&format!(
+                "ALTER TABLE {} SET (timescaledb.compress, \
+                 timescaledb.compress_segmentby = '{}', \
+                 timescaledb.compress_orderby = '{}')",
+                table, hypertable.segment_by, hypertable.order_by
+            ))
+            .execute(&mut **tx)
+            .await?;
+        }
+        sqlx::query("SELECT add_compression_policy($1::regclass, $2::interval)")
+            .bind(table)
+            .bind(&config.compress_after)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    sqlx::query("SELECT remove_retention_policy($1::regclass, if_exists => true)")
+        .bind(table)
+        .execute(&mut **tx)
+        .await?;
+    if let Some(retention) = &config.retention {
+        sqlx::query("SELECT add_retention_policy($1::regclass, $2::interval)")
+            .bind(table)
+            .bind(retention)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn current_policies(pool: &PgPool, table: &str) -> Result<Policies> {
+    let chunk_interval: Option<(String,)> = sqlx::query_as(
+        "SELECT time_interval::text FROM timescaledb_information.dimensions \
+         WHERE hypertable_name = $1 AND column_name = '_time'",
+    )
+    .bind(table)
+    .fetch_optional(pool)
+    .await?;
+
+    let compression_enabled: Option<(bool,)> = sqlx::query_as(
+        "SELECT compression_enabled FROM timescaledb_information.hypertables \
+         WHERE hypertable_name = $1",
+    )
+    .bind(table)
+    .fetch_optional(pool)
+    .await?;
+
+    let segment_by: Vec<(String,)> = sqlx::query_as(
+        "SELECT attname::text FROM timescaledb_information.compression_settings \
+         WHERE hypertable_name = $1 AND segmentby_column_index IS NOT NULL \
+         ORDER BY segmentby_column_index",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+
+    let order_by: Vec<(String,)> = sqlx::query_as(
+        "SELECT attname::text || CASE WHEN orderby_asc THEN '' ELSE ' DESC' END \
+         FROM timescaledb_information.compression_settings \
+         WHERE hypertable_name = $1 AND orderby_column_index IS NOT NULL \
+         ORDER BY orderby_column_index",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+
+    let jobs: Vec<(String, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT proc_name::text, config->>'compress_after', config->>'drop_after' \
+         FROM timescaledb_information.jobs WHERE hypertable_name = $1",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+    let job_setting = |proc_name: &str| {
+        jobs.iter()
+            .find(|(name, _, _)| name == proc_name)
+            .map(|(_, compress_after, drop_after)| {
+                compress_after
+                    .clone()
+                    .or_else(|| drop_after.clone())
+                    .unwrap_or_default()
+            })
+    };
+
+    Ok(Policies {
+        chunk_interval: chunk_interval.map(|(interval,)| interval),
+        compression_enabled: compression_enabled.is_some_and(|(enabled,)| enabled),
+        segment_by: segment_by.into_iter().map(|(column,)| column).collect(),
+        order_by: order_by.into_iter().map(|(column,)| column).collect(),
+        compress_after: job_setting("policy_compression"),
+        retention: job_setting("policy_retention"),
+    })
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+
+use crate::air_models::air_model::WELL_KNOWN_VARIABLES;
+use crate::error::Result;
+
+/// Period the verification scores are grouped by.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Window {
+    /// One score over the whole selected range
+    All,
+    Day,
+    Week,
+    Month,
+}
+
+impl Window {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Window::All => "all",
+            Window::Day => "day",
+            Window::Week => "week",
+            Window::Month => "month",
+        }
+    }
+}
+
+/// Which forecasts to score and how to group them.
+pub struct VerifyFilter {
+    pub start: Option<NaiveDate>,
+    /// Exclusive
+    pub end: Option<NaiveDate>,
+    /// Every location when empty
+    pub locations: Vec<String>,
+    /// Every well-known variable when empty
+    pub pollutants: Vec<String>,
+    /// Lead hours per group, e.g. 24 to score each forecast day
+    pub lead_step: i32,
+    pub window: Window,
+}
+
+/// Scores for one location, pollutant, lead range and window. Errors are
+/// forecast minus observation.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Skill {
+    pub location: String,
+    pub pollutant: String,
+    pub lead_from: i32,
+    pub lead_to: i32,
+    /// Start of the window, `None` when scoring the whole range
+    pub window_start: Option<DateTime<Utc>>,
+    /// Forecast hours with a matching observation
+    pub samples: i64,
+    pub bias: Option<f64>,
+    pub mae: Option<f64>,
+    pub rmse: Option<f64>,
+    /// `None` with fewer than two samples or no variation
+    pub correlation: Option<f64>,
+}
+
+/// Only hours with both a forecast and an observed value count.
+pub async fn verify(pool: &PgPool, filter: &VerifyFilter) -> Result<Vec<Skill>> {
+    Ok(sqlx::query_as(&skill_query())
+        .bind(filter.window.as_str())
+        .bind(filter.lead_step)
+        .bind(day_start(filter.start))
+        .bind(day_start(filter.end))
+        .bind(&filter.locations)
+        .bind(&filter.pollutants)
+        .fetch_all(pool)
+        .await?)
+}
+
+fn skill_query() -> String {
+    // One row per pollutant and forecast hour, so every pollutant is scored
+    // by the same aggregates
+    let pairs: Vec<String> = WELL_KNOWN_VARIABLES
+        .iter()
+        .map(|v| format!("('{v}', f.{v}::float8, o.{v}::float8)"))
+        .collect();
+    format!(
+        r#"
+        SELECT
+            f.location,
+            v.pollutant,
+            (f.lead_hours - 1) / $2 * $2 + 1 AS lead_from,
+            ((f.lead_hours - 1) / $2 + 1) * $2 AS lead_to,
+            CASE WHEN $1 = 'all' THEN NULL ELSE date_trunc($1, f._time, 'UTC') END AS window_start,
+            count(*) AS samples,
+            avg(v.forecast - v.observed) AS bias,
+            avg(abs(v.forecast - v.observed)) AS mae,
+            sqrt(avg((v.forecast - v.observed) ^ 2)) AS rmse,
+            corr(v.forecast, v.observed) AS correlation
+        FROM air_quality_forecast f
+        JOIN air_quality o ON o.location = f.location AND o._time = f._time
+        CROSS JOIN LATERAL (VALUES
+            {}
+        ) AS v (pollutant, forecast, observed)
+        WHERE v.forecast IS NOT NULL AND v.observed IS NOT NULL
+            AND ($3::timestamptz IS NULL OR f._time >= $3)
+            AND ($4::timestamptz IS NULL OR f._time < $4)
+            AND (cardinality($5::text[]) = 0 OR f.location = ANY($5))
+            AND (cardinality($6::text[]) = 0 OR v.pollutant = ANY($6))
+        GROUP BY 1, 2, 3, 4, 5
+        ORDER BY 1, 2, 3, 5
+    "#,
+        pairs.join(",\n            ")
+    )
+}
+
+fn day_start(date: Option<NaiveDate>) -> Option<DateTime<Utc>> {
+    date.map(|d| d.and_time(Default::default()).and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    #[test]
+    fn every_well_known_variable_is_scored() {
+        let query = skill_query();
+        for variable in WELL_KNOWN_VARIABLES {
+            let pair = format!("('{variable}', f.{variable}::float8, o.{variable}::float8)");
+            assert!(query.contains(&pair), "{} is not scored", variable);
+        }
+    }
+
+    #[test]
+    fn ranges_are_whole_utc_days() {
+        assert_eq!(
+            day_start(Some(date("2024-03-01"))),
+            Some("2024-03-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(day_start(None), None);
+    }
+}
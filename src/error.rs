@@ -0,0 +1,178 @@
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Stored without the URL, which can carry the API key.
+    #[error("request failed: {0}")]
+    Transport(reqwest::Error),
+
+    /// HTTP 429, with the delay the server asked for if it sent one.
+    #[error("rate limited by the API (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// A non-success response without a recognizable error body.
+    #[error("API responded with {status}: {body}")]
+    HttpStatus { status: StatusCode, body: String },
+
+    /// Open-Meteo's own error report, `{"error": true, "reason": ...}`.
+    #[error("API rejected request with {status}: {reason}")]
+    Api { status: StatusCode, reason: String },
+
+    #[error("failed to decode JSON: {0}")]
+    Deserialize(#[from] serde_json::Error),
+
+    /// A response variable without exactly one value per timestamp.
+    #[error("response variable '{variable}' has {found} values for {expected} timestamps")]
+    MisalignedColumn {
+        variable: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("invalid timestamp '{value}': {source}")]
+    TimeParse {
+        value: String,
+        source: chrono::ParseError,
+    },
+
+    #[error("kafka error: {0}")]
+    Kafka(#[from] KafkaError),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("migration failed: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    /// The database has not been migrated to the schema this build expects.
+    #[error("database schema is behind, {pending} migrations pending (run `db migrate`)")]
+    SchemaBehind { pending: usize },
+
+    /// Every recorded recent response has been replayed.
+    #[error("no recorded responses left for {location}")]
+    RecordingExhausted { location: String },
+
+    /// A backfill range the API cannot serve.
+    #[error("invalid backfill range: {0}")]
+    InvalidRange(String),
+
+    /// Something the API or the chosen data set cannot do.
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+
+    #[error("configuration error: {0}")]
+    Config(#[from] config::ConfigError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e.without_url())
+    }
+}
+
+impl Error {
+    /// Whether repeating the operation may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(e) => !e.is_decode(),
+            Error::RateLimited { .. } => true,
+            Error::HttpStatus { status, .. } | Error::Api { status, .. } => {
+                status.is_server_error()
+            }
+            Error::Database(e) => is_transient_db_error(e),
+            Error::Kafka(e) => e.rdkafka_error_code().is_some_and(|code| {
+                matches!(
+                    code,
+                    RDKafkaErrorCode::QueueFull
+                        | RDKafkaErrorCode::MessageTimedOut
+                        | RDKafkaErrorCode::AllBrokersDown
+                        | RDKafkaErrorCode::BrokerTransportFailure
+                        | RDKafkaErrorCode::RequestTimedOut
+                )
+            }),
+            Error::Deserialize(_)
+            | Error::MisalignedColumn { .. }
+            | Error::TimeParse { .. }
+            | Error::Migrate(_)
+            | Error::SchemaBehind { .. }
+            | Error::InvalidRange(_)
+            | Error::Unsupported(_)
+            | Error::Config(_)
+            | Error::RecordingExhausted { .. }
+            | Error::Io(_) => false,
+        }
+    }
+
+    /// Exit code following the BSD sysexits convention.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) | Error::SchemaBehind { .. } => 78,
+            Error::InvalidRange(_) | Error::Unsupported(_) => 64,
+            Error::Io(_) | Error::RecordingExhausted { .. } => 74,
+            Error::Deserialize(_) | Error::MisalignedColumn { .. } | Error::TimeParse { .. } => 65,
+            Error::Transport(_)
+            | Error::RateLimited { .. }
+            | Error::HttpStatus { .. }
+            | Error::Api { .. }
+            | Error::Kafka(_)
+            | Error::Database(_)
+            | Error::Migrate(_) => 69,
+        }
+    }
+}
+
+fn is_transient_db_error(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // Only closed on shutdown, and never reopened
+        sqlx::Error::PoolClosed => false,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            // 08: connection exception, 40: transaction rollback,
+            // 53: insufficient resources, 57P0x: operator intervention
+            code.starts_with("08")
+                || code.starts_with("40")
+                || code.starts_with("53")
+                || code.starts_with("57P0")
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn transport_errors_do_not_show_the_api_key() {
+        // Accepts the connection but never answers, so the request times out
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/v1/air-quality?apikey=secret",
+            listener.local_addr().unwrap()
+        );
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let e = client.get(&url).send().await.unwrap_err();
+        assert!(e.to_string().contains("secret"));
+
+        let e = Error::from(e);
+        assert!(matches!(e, Error::Transport(_)));
+        assert!(!format!("{} {:?}", e, e).contains("secret"));
+        assert!(e.is_retryable());
+    }
+
+    #[test]
+    fn a_closed_pool_is_not_retried() {
+        assert!(Error::Database(sqlx::Error::PoolTimedOut).is_retryable());
+        assert!(!Error::Database(sqlx::Error::PoolClosed).is_retryable());
+    }
+}
@@ -1,57 +1,314 @@
-use crate::air_models::AirQualityHourly;
-use crate::traits::data_loader::Persistable;
-use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::message::Message;
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use crate::config::ConsumerConfig;
+use crate::error::Result;
+use crate::kafka::dlq::{send_to_dlq, DeadLetterKind};
+use crate::shutdown::Shutdown;
+use crate::traits::data_fetcher::HourlyRecord;
+use crate::traits::data_loader::{ConflictStrategy, Persistable};
+use crate::traits::message_bus::{BusMessage, MessagePublisher, MessageSubscriber};
+use sqlx::PgPool;
 use std::time::Duration;
-use tokio_stream::StreamExt;
-use tracing::{error, info};
-
-pub async fn run_consumer(broker: &str, db_url: &str) {
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("bootstrap.servers", broker)
-        .set("group.id", "hello-group")
-        .set("auto.offset.reset", "earliest")
-        .create()
-        .expect("Failed to create consumer");
-
-    consumer
-        .subscribe(&["weather-data"])
-        .expect("Failed to subscribe to topic");
-
-    // let config: AppConfig = load_config().expect("Failed to load config");
-    // let db_url = config.database.db_url.as_str();
-
-    let pool: PgPool = PgPoolOptions::new()
-        .max_connections(10)
-        .acquire_timeout(Duration::from_secs(20))
-        .connect(db_url)
-        .await
-        .expect("Failed to establish db connection");
+use tokio::time::sleep;
+use tracing::{error, info, warn};
 
-    info!(target: "consumer", "[Consumer] Listening for messages...");
+/// Writes a batch, retrying transient failures with exponential backoff.
+/// Gives up with the last error when shutdown is requested during a backoff.
+pub async fn save_with_retry<R>(
+    batch: &Vec<R>,
+    pool: &PgPool,
+    on_conflict: ConflictStrategy,
+    retry: &ConsumerConfig,
+    shutdown: &Shutdown,
+) -> Result<()>
+where
+    Vec<R>: Persistable,
+{
+    let mut attempt = 0;
+    loop {
+        match batch.save_to_db(pool, on_conflict).await {
+            Ok(()) => return Ok(()),
+            Err(e) if e.is_retryable() && attempt + 1 < retry.db_max_retries => {
+                let delay = retry
+                    .db_retry_base_ms
+                    .saturating_mul(1 << attempt.min(16))
+                    .min(retry.db_retry_max_ms);
+                attempt += 1;
+                warn!(target: "consumer",
+                    "[Consumer] Database write failed (attempt {}/{}), retrying in {} ms: {}",
+                    attempt, retry.db_max_retries, delay, e
+                );
+                let mut shutdown = shutdown.clone();
+                tokio::select! {
+                    _ = sleep(Duration::from_millis(delay)) => {}
+                    _ = shutdown.triggered() => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    let mut message_stream = consumer.stream();
+async fn commit<S: MessageSubscriber + ?Sized>(subscriber: &mut S, msg: &BusMessage) {
+    if let Err(e) = subscriber.commit(msg).await {
+        error!(target: "consumer", "[Consumer] Failed to commit offset {}: {}", msg.offset, e);
+    }
+}
+
+/// Commits each offset only once its batch is stored or dead-lettered, and
+/// fails without committing when neither is possible.
+pub async fn run_consumer<R, S, P>(
+    subscriber: &mut S,
+    dlq: &P,
+    pool: &PgPool,
+    dlq_topic: &str,
+    on_conflict: ConflictStrategy,
+    retry: &ConsumerConfig,
+    mut shutdown: Shutdown,
+) -> Result<()>
+where
+    R: HourlyRecord,
+    Vec<R>: Persistable,
+    S: MessageSubscriber + ?Sized,
+    P: MessagePublisher + ?Sized,
+{
+    info!(target: "consumer", "[Consumer] Listening for messages...");
 
-    while let Some(result) = message_stream.next().await {
+    loop {
+        let result = tokio::select! {
+            next = subscriber.next() => match next {
+                Some(result) => result,
+                None => break,
+            },
+            _ = shutdown.triggered() => break,
+        };
         match result {
             Ok(msg) => {
-                if let Some(Ok(payload)) = msg.payload_view::<str>() {
-                    match serde_json::from_str::<Vec<AirQualityHourly>>(payload) {
+                let dead_letter = match msg.payload.as_deref().map(std::str::from_utf8) {
+                    None => None,
+                    Some(Err(e)) => {
+                        error!(target: "consumer", "[Consumer] payload is not valid UTF-8: {}", e);
+                        Some((DeadLetterKind::Parse, e.to_string()))
+                    }
+                    Some(Ok(payload)) => match serde_json::from_str::<Vec<R>>(payload) {
                         Ok(parsed) => {
-                            if let Err(e) = parsed.save_to_db(&pool).await {
-                                error!(target: "consumer", "[Consumer] failed to insert record: {}", e)
+                            match save_with_retry(&parsed, pool, on_conflict, retry, &shutdown)
+                                .await
+                            {
+                                Ok(()) => None,
+                                Err(e) if e.is_retryable() => {
+                                    error!(target: "consumer",
+                                        "[Consumer] Database unavailable, stopping at offset {}: {}",
+                                        msg.offset, e
+                                    );
+                                    return Err(e);
+                                }
+                                Err(e) => {
+                                    error!(target: "consumer", "[Consumer] failed to insert record: {}", e);
+                                    Some((DeadLetterKind::Persist, e.to_string()))
+                                }
                             }
                         }
                         Err(e) => {
-                            error!(target: "consumer", "[Consumer] failed to parse JSON: {}", e)
+                            error!(target: "consumer", "[Consumer] failed to parse JSON: {}", e);
+                            Some((DeadLetterKind::Parse, e.to_string()))
                         }
+                    },
+                };
+
+                if let Some((kind, reason)) = dead_letter {
+                    if let Err(e) = send_to_dlq(dlq, dlq_topic, &msg, kind, &reason).await {
+                        error!(target: "consumer",
+                            "[Consumer] Failed to dead-letter offset {}, stopping: {}",
+                            msg.offset, e
+                        );
+                        return Err(e);
                     }
+                    info!(target: "consumer",
+                        "[Consumer] Moved offset {} to {}", msg.offset, dlq_topic
+                    );
                 }
+                commit(subscriber, &msg).await;
             }
-            Err(e) => error!(target: "consumer", "[Consumer] Kafka Error: {}", e),
+            Err(e) => error!(target: "consumer", "[Consumer] Bus Error: {}", e),
         }
         info!(target: "consumer", "[Consumer] Waiting for next message ...")
     }
+
+    // Every message handed out so far has been fully processed, so the
+    // commits made so far are safe to store before leaving.
+    info!(target: "consumer", "[Consumer] Shutting down, committing offsets");
+    dlq.flush().await?;
+    subscriber.close().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus;
+    use crate::bus::channel::{ChannelPublisher, ChannelSubscriber};
+    use crate::error::Error;
+    use crate::kafka::dlq::{
+        HEADER_ERROR_KIND, HEADER_ERROR_MESSAGE, HEADER_SOURCE_OFFSET, HEADER_SOURCE_PARTITION,
+        HEADER_SOURCE_TOPIC, HEADER_TIMESTAMP,
+    };
+    use crate::testing::{quick_retry, reading, unused_pool, Reading};
+    use async_trait::async_trait;
+
+    /// Remembers committed offsets, which the channel bus does not keep.
+    struct Committed {
+        inner: ChannelSubscriber,
+        offsets: Vec<i64>,
+    }
+
+    #[async_trait]
+    impl MessageSubscriber for Committed {
+        async fn next(&mut self) -> Option<Result<BusMessage>> {
+            self.inner.next().await
+        }
+
+        async fn commit(&mut self, msg: &BusMessage) -> Result<()> {
+            self.offsets.push(msg.offset);
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn send(publisher: &ChannelPublisher, batch: &[Reading]) {
+        let payload = serde_json::to_vec(batch).unwrap();
+        let key = batch[0].location.as_str();
+        publisher
+            .publish("air-quality", Some(key), &payload, &[])
+            .await
+            .unwrap();
+    }
+
+    /// Runs the consumer over everything published to `publisher` and
+    /// returns the committed offsets and the dead-lettered messages.
+    async fn consume(
+        publisher: ChannelPublisher,
+        subscriber: ChannelSubscriber,
+    ) -> (Result<()>, Vec<i64>, Vec<BusMessage>) {
+        drop(publisher);
+        let mut subscriber = Committed {
+            inner: subscriber,
+            offsets: Vec::new(),
+        };
+        let (dlq, mut dead_letters) = bus::channel(16);
+        let pool = unused_pool();
+        let retry = quick_retry();
+
+        let result = run_consumer::<Reading, _, _>(
+            &mut subscriber,
+            &dlq,
+            &pool,
+            "air-quality-dlq",
+            ConflictStrategy::Skip,
+            &retry,
+            Shutdown::never(),
+        )
+        .await;
+        drop(dlq);
+
+        let mut messages = Vec::new();
+        while let Some(msg) = dead_letters.next().await {
+            messages.push(msg.unwrap());
+        }
+        (result, subscriber.offsets, messages)
+    }
+
+    #[tokio::test]
+    async fn offsets_are_committed_only_after_the_batch_is_saved() {
+        let (publisher, subscriber) = bus::channel(16);
+        send(
+            &publisher,
+            &[reading("berlin", "2024-01-01T00:00:00Z", 1.0)],
+        )
+        .await;
+        send(
+            &publisher,
+            &[reading("offline", "2024-01-01T00:00:00Z", 1.0)],
+        )
+        .await;
+        send(
+            &publisher,
+            &[reading("berlin", "2024-01-01T01:00:00Z", 1.0)],
+        )
+        .await;
+
+        let (result, committed, dead_letters) = consume(publisher, subscriber).await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(committed, [0]);
+        assert!(dead_letters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bad_payloads_are_dead_lettered_with_their_headers() {
+        let (publisher, subscriber) = bus::channel(16);
+        publisher
+            .publish("air-quality", Some("berlin"), b"not json", &[])
+            .await
+            .unwrap();
+        send(
+            &publisher,
+            &[reading("berlin", "2024-01-01T00:00:00Z", -1.0)],
+        )
+        .await;
+        send(
+            &publisher,
+            &[reading("berlin", "2024-01-01T01:00:00Z", 1.0)],
+        )
+        .await;
+
+        let (result, committed, dead_letters) = consume(publisher, subscriber).await;
+        result.unwrap();
+        assert_eq!(committed, [0, 1, 2]);
+        assert_eq!(dead_letters.len(), 2);
+
+        let parse = &dead_letters[0];
+        assert_eq!(parse.topic, "air-quality-dlq");
+        assert_eq!(parse.key.as_deref(), Some("berlin"));
+        assert_eq!(parse.payload.as_deref(), Some(&b"not json"[..]));
+        assert_eq!(parse.header(HEADER_ERROR_KIND), Some("parse"));
+        assert_eq!(parse.header(HEADER_SOURCE_TOPIC), Some("air-quality"));
+        assert_eq!(parse.header(HEADER_SOURCE_PARTITION), Some("0"));
+        assert_eq!(parse.header(HEADER_SOURCE_OFFSET), Some("0"));
+        assert!(parse.header(HEADER_TIMESTAMP).is_some());
+
+        let persist = &dead_letters[1];
+        assert_eq!(persist.header(HEADER_ERROR_KIND), Some("persist"));
+        assert_eq!(persist.header(HEADER_SOURCE_OFFSET), Some("1"));
+        assert!(persist
+            .header(HEADER_ERROR_MESSAGE)
+            .is_some_and(|message| message.contains("check constraint")));
+    }
+
+    #[tokio::test]
+    async fn shutdown_cuts_a_retry_backoff_short() {
+        let batch = vec![reading("offline", "2024-01-01T00:00:00Z", 1.0)];
+        let retry = ConsumerConfig {
+            db_max_retries: 5,
+            db_retry_base_ms: 60_000,
+            db_retry_max_ms: 60_000,
+        };
+
+        let saved = tokio::time::timeout(
+            Duration::from_secs(5),
+            save_with_retry(
+                &batch,
+                &unused_pool(),
+                ConflictStrategy::Skip,
+                &retry,
+                &Shutdown::requested(),
+            ),
+        )
+        .await
+        .expect("the backoff was not cut short");
+        assert!(matches!(
+            saved,
+            Err(Error::Database(sqlx::Error::PoolTimedOut))
+        ));
+    }
 }
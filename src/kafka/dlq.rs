@@ -0,0 +1,194 @@
+use chrono::Utc;
+use clap::ValueEnum;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{error, info};
+
+use crate::error::Result;
+use crate::traits::message_bus::{BusMessage, MessagePublisher, MessageSubscriber};
+
+pub const HEADER_ERROR_KIND: &str = "dlq.error.kind";
+pub const HEADER_ERROR_MESSAGE: &str = "dlq.error.message";
+pub const HEADER_SOURCE_TOPIC: &str = "dlq.source.topic";
+pub const HEADER_SOURCE_PARTITION: &str = "dlq.source.partition";
+pub const HEADER_SOURCE_OFFSET: &str = "dlq.source.offset";
+pub const HEADER_TIMESTAMP: &str = "dlq.timestamp";
+
+/// Why a message ended up in the dead-letter topic.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum DeadLetterKind {
+    /// The payload could not be decoded into a batch.
+    Parse,
+    /// The batch was rejected by the database, e.g. by a constraint.
+    Persist,
+}
+
+impl DeadLetterKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterKind::Parse => "parse",
+            DeadLetterKind::Persist => "persist",
+        }
+    }
+}
+
+/// Keeps the payload and key, and adds headers describing the failure.
+pub async fn send_to_dlq<P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    dlq_topic: &str,
+    msg: &BusMessage,
+    kind: DeadLetterKind,
+    reason: &str,
+) -> Result<()> {
+    let partition = msg.partition.to_string();
+    let offset = msg.offset.to_string();
+    let timestamp = Utc::now().to_rfc3339();
+    let headers = [
+        (HEADER_ERROR_KIND, kind.as_str()),
+        (HEADER_ERROR_MESSAGE, reason),
+        (HEADER_SOURCE_TOPIC, msg.topic.as_str()),
+        (HEADER_SOURCE_PARTITION, partition.as_str()),
+        (HEADER_SOURCE_OFFSET, offset.as_str()),
+        (HEADER_TIMESTAMP, timestamp.as_str()),
+    ];
+
+    publisher
+        .publish(
+            dlq_topic,
+            msg.key.as_deref(),
+            msg.payload.as_deref().unwrap_or_default(),
+            &headers,
+        )
+        .await
+}
+
+/// Replaying one `kind` of messages gets a group of its own.
+pub fn replay_group_id(group_id: &str, kind: Option<DeadLetterKind>) -> String {
+    match kind {
+        Some(kind) => format!("{}-dlq-replay-{}", group_id, kind.as_str()),
+        None => format!("{}-dlq-replay", group_id),
+    }
+}
+
+/// Moves dead-lettered messages back onto `topic` until `idle_timeout` passes
+/// without a new one.
+pub async fn run_dlq_replay<S, P>(
+    subscriber: &mut S,
+    publisher: &P,
+    topic: &str,
+    kind: Option<DeadLetterKind>,
+    idle_timeout: Duration,
+) -> Result<()>
+where
+    S: MessageSubscriber + ?Sized,
+    P: MessagePublisher + ?Sized,
+{
+    let mut replayed = 0;
+    while let Ok(Some(result)) = timeout(idle_timeout, subscriber.next()).await {
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!(target: "consumer", "[DLQ] Bus Error: {}", e);
+                continue;
+            }
+        };
+
+        let message_kind = msg.header(HEADER_ERROR_KIND).unwrap_or_default();
+        if kind.is_some_and(|kind| kind.as_str() != message_kind) {
+            if let Err(e) = subscriber.commit(&msg).await {
+                error!(target: "consumer", "[DLQ] Failed to commit offset {}: {}", msg.offset, e);
+            }
+            continue;
+        }
+
+        let payload = msg.payload.as_deref().unwrap_or_default();
+        match publisher
+            .publish(topic, msg.key.as_deref(), payload, &[])
+            .await
+        {
+            Ok(()) => {
+                info!(target: "consumer",
+                    "[DLQ] Replayed {} message from offset {} (failed with: {})",
+                    message_kind,
+                    msg.offset,
+                    msg.header(HEADER_ERROR_MESSAGE).unwrap_or_default()
+                );
+                replayed += 1;
+            }
+            Err(e) => {
+                // Leave the offset uncommitted so the next replay retries it
+                error!(target: "consumer", "[DLQ] Failed to replay offset {}: {}", msg.offset, e);
+                return Err(e);
+            }
+        }
+        if let Err(e) = subscriber.commit(&msg).await {
+            error!(target: "consumer", "[DLQ] Failed to commit offset {}: {}", msg.offset, e);
+        }
+    }
+    publisher.flush().await?;
+    subscriber.close().await?;
+    info!(target: "consumer", "[DLQ] Replay finished, {} messages sent to {}", replayed, topic);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus;
+
+    fn failed(offset: i64, key: &str, payload: &[u8]) -> BusMessage {
+        BusMessage {
+            topic: "air-quality".to_string(),
+            partition: 0,
+            offset,
+            key: Some(key.to_string()),
+            payload: Some(payload.to_vec()),
+            headers: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_republishes_dead_letters_of_the_chosen_kind() {
+        let (dlq, mut dead_letters) = bus::channel(16);
+        let bad_json = failed(0, "berlin", b"not json");
+        let rejected = failed(1, "paris", b"[]");
+        send_to_dlq(
+            &dlq,
+            "air-quality-dlq",
+            &bad_json,
+            DeadLetterKind::Parse,
+            "bad JSON",
+        )
+        .await
+        .unwrap();
+        send_to_dlq(
+            &dlq,
+            "air-quality-dlq",
+            &rejected,
+            DeadLetterKind::Persist,
+            "rejected",
+        )
+        .await
+        .unwrap();
+        drop(dlq);
+
+        let (publisher, mut replayed) = bus::channel(16);
+        run_dlq_replay(
+            &mut dead_letters,
+            &publisher,
+            "air-quality",
+            Some(DeadLetterKind::Persist),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+        drop(publisher);
+
+        let msg = replayed.next().await.unwrap().unwrap();
+        assert_eq!(msg.topic, "air-quality");
+        assert_eq!(msg.key.as_deref(), Some("paris"));
+        assert_eq!(msg.payload.as_deref(), Some(&b"[]"[..]));
+        assert!(msg.headers.is_empty());
+        assert!(replayed.next().await.is_none());
+    }
+}
@@ -1,5 +1,9 @@
 pub mod consumer;
+pub mod dlq;
 pub mod producer;
 
 pub use consumer::run_consumer;
-pub use producer::{run_historical_producer, run_recent_producer};
+pub use dlq::run_dlq_replay;
+pub use producer::{
+    run_forecast_producer, run_gap_fill, run_historical_producer, run_recent_producer,
+};
@@ -1,98 +1,643 @@
-use chrono::{Duration as TimeDuration, NaiveDate, Utc};
-use rdkafka::config::ClientConfig;
-use rdkafka::producer::{FutureProducer, FutureRecord};
+use chrono::{DateTime, Duration as TimeDuration, Utc};
+use futures::future::join_all;
+use std::collections::{HashMap, VecDeque};
 use std::time::Duration;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::traits::data_fetcher::DataFetcher;
+use crate::backfill::{plan_windows, BackfillRange, DateWindow, Direction, DEFAULT_WINDOW_DAYS};
+use crate::checkpoint::CheckpointStore;
+use crate::error::{Error, Result};
+use crate::schedule::Schedule;
+use crate::shutdown::Shutdown;
+use crate::traits::data_fetcher::{DataFetcher, HourlyRecord};
+use crate::traits::message_bus::MessagePublisher;
 
-const MAX_DAYS: i64 = 91;
-const FETCH_INTERVAL_SECS: u64 = 5;
-const EARLIEST_DATE: &str = "2023-01-01";
+/// Requeues of a failed historical window before the backfill gives up on it.
+const MAX_WINDOW_REQUEUES: u32 = 3;
+/// Longest single sleep while waiting for the next scheduled fetch.
+const WALL_CLOCK_CHECK: Duration = Duration::from_secs(60);
 
-pub async fn run_historical_producer<F: DataFetcher + Sync>(broker: &str, fetcher: &F) {
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", broker)
-        .create()
-        .expect("Error connecting to kafka client");
+async fn flush<P: MessagePublisher + ?Sized>(publisher: &P) {
+    if let Err(e) = publisher.flush().await {
+        error!(target: "producer", "[Producer] Failed to flush pending messages: {}", e);
+    }
+}
 
-    let mut current_end = Utc::now().date_naive();
-    let earliest =
-        NaiveDate::parse_from_str(EARLIEST_DATE, "%Y-%m-%d").expect("Invalid hardcoded date");
+/// Keyed by location, so a location's readings share a partition.
+async fn send_batch<R: HourlyRecord, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    location: &str,
+    hourly: &[R],
+) -> Result<()> {
+    let batch_payload = serde_json::to_string(hourly).inspect_err(|e| {
+        error!(target: "producer", "Failed to serialize batch for {}: {}", location, e);
+    })?;
+    publisher
+        .publish(topic, Some(location), batch_payload.as_bytes(), &[])
+        .await
+        .inspect_err(|e| error!(target: "producer", "[Producer] Error: {:?}", e))?;
+    info!(target: "producer", "[Producer] Delivered {} readings for {}", hourly.len(), location);
+    Ok(())
+}
 
-    while current_end > earliest {
-        let current_start = (current_end - TimeDuration::days(MAX_DAYS)).max(earliest);
+pub async fn run_historical_producer<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetchers: &[F],
+    range: &BackfillRange,
+    pause: Duration,
+    checkpoints: &CheckpointStore,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let results = join_all(fetchers.iter().map(|fetcher| {
+        historical_for_location(
+            publisher,
+            topic,
+            fetcher,
+            range,
+            pause,
+            checkpoints,
+            shutdown.clone(),
+        )
+    }))
+    .await;
+    flush(publisher).await;
+    info!(target: "producer", "[Producer] Fetching data complete");
+    results.into_iter().collect()
+}
 
-        let start_date = current_start.format("%Y-%m-%d").to_string();
-        let end_date = current_end.format("%Y-%m-%d").to_string();
+async fn historical_for_location<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetcher: &F,
+    range: &BackfillRange,
+    pause: Duration,
+    checkpoints: &CheckpointStore,
+    mut shutdown: Shutdown,
+) -> Result<()> {
+    let location = &fetcher.location().name;
+    let checkpoint = checkpoints
+        .start(location, range, Utc::now().date_naive())
+        .await
+        .inspect_err(|e| {
+            error!(target: "producer", "[Producer] Failed to read checkpoint for {}: {}", location, e)
+        })?;
+
+    let pending = checkpoint.pending();
+    info!(target: "producer",
+        "[Producer] Starting {:?} backfill for {} (tags: {:?}) over {} to {}, {} of {} windows already done",
+        checkpoint.direction,
+        location,
+        fetcher.location().tags,
+        checkpoint.start,
+        checkpoint.end,
+        checkpoint.completed.len(),
+        checkpoint.completed.len() + pending.len()
+    );
+
+    // Windows that fail are put back at the end of the queue, so a passing
+    // outage costs a retry later instead of a hole in the backfill.
+    let mut queue: VecDeque<(DateWindow, u32)> =
+        pending.into_iter().map(|window| (window, 0)).collect();
+    let mut failed = 0;
+    let mut last_error = None;
+    while let Some((window, requeues)) = queue.pop_front() {
+        if shutdown.is_triggered() {
+            info!(target: "producer",
+                "[Producer] Stopping backfill for {}, {} windows left for the next run",
+                location,
+                queue.len() + 1
+            );
+            return Ok(());
+        }
+        let start_date = window.start.format("%Y-%m-%d").to_string();
+        let end_date = window.end.format("%Y-%m-%d").to_string();
 
         info!(target: "producer",
-            "[Producer] Fetching data from {} to {}",
-            start_date, end_date
+            "[Producer] Fetching data for {} from {} to {}",
+            location, start_date, end_date
         );
-        match fetcher.fetch_historical(&start_date, &end_date).await {
-            Ok(hourly) => match serde_json::to_string(&hourly) {
-                Ok(batch_payload) => {
-                    let record = FutureRecord::to("weather-data")
-                        .payload(&batch_payload)
-                        .key("key");
-                    match producer.send(record, Duration::from_secs(0)).await {
-                        Ok(delivery) => {
-                            info!(target: "producer", "[Producer] Delivered: {:?}", delivery)
-                        }
-                        Err((e, _)) => error!(target: "producer", "[Producer] Error: {:?}", e),
-                    }
-                }
-                Err(e) => {
-                    error!(target: "producer", "Failed to serialize air quality batch: {}", e)
+        // Abandoning a fetch is harmless since nothing has been sent yet;
+        // the window stays unfinished and is picked up by the next run.
+        let fetched = tokio::select! {
+            fetched = fetcher.fetch_historical(&start_date, &end_date) => fetched,
+            _ = shutdown.triggered() => {
+                queue.push_front((window, requeues));
+                continue;
+            }
+        };
+        // The error a window failed with, and whether trying it again later
+        // may help. Sending is always worth another try.
+        let failure = match fetched {
+            Ok(hourly) => match send_batch(publisher, topic, location, &hourly).await {
+                Ok(()) => {
+                    // Going on without a record of the progress would have
+                    // the next run publish every window again
+                    checkpoints
+                        .mark_complete(location, window)
+                        .await
+                        .inspect_err(|e| {
+                            error!(target: "producer", "[Producer] Failed to save checkpoint for {}: {}", location, e)
+                        })?;
+                    None
                 }
+                Err(e) => Some((e, true)),
             },
             Err(e) => {
                 error!(target: "producer",
-                    "[Producer] Failed to fetch air quality data from {} to {}: {}",
-                    start_date, end_date, e
+                    "[Producer] Failed to fetch data for {} from {} to {}: {}",
+                    location, start_date, end_date, e
+                );
+                let retryable = e.is_retryable();
+                Some((e, retryable))
+            }
+        };
+
+        match failure {
+            Some((_, true)) if requeues < MAX_WINDOW_REQUEUES => {
+                info!(target: "producer",
+                    "[Producer] Re-queued {} to {} for {}", start_date, end_date, location
                 );
+                queue.push_back((window, requeues + 1));
             }
+            Some((e, _)) => {
+                failed += 1;
+                last_error = Some(e);
+            }
+            None => {}
+        }
+        tokio::select! {
+            _ = sleep(pause) => {}
+            _ = shutdown.triggered() => {}
         }
-        current_end = current_start - TimeDuration::days(1);
-        sleep(Duration::from_secs(FETCH_INTERVAL_SECS)).await;
     }
-    info!(target: "producer", "[Producer] Fetching data complete")
+
+    // The run fails with the last window's error, so the exit code tells
+    // what kind of trouble the backfill ran into
+    if let Some(e) = last_error {
+        error!(target: "producer",
+            "[Producer] Backfill for {} left {} windows unfetched, rerun to retry them",
+            location, failed
+        );
+        return Err(e);
+    }
+    info!(target: "producer", "[Producer] Backfill for {} complete", location);
+    Ok(())
 }
 
-pub async fn run_recent_producer<F: DataFetcher + Sync>(broker: &str, fetcher: &F) {
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", broker)
-        .create()
-        .expect("Error connecting to kafka client");
+pub async fn run_recent_producer<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetchers: &[F],
+    schedule: &Schedule,
+    last_ingested: &HashMap<String, DateTime<Utc>>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let results = join_all(fetchers.iter().map(|fetcher| {
+        let last = last_ingested.get(&fetcher.location().name).copied();
+        recent_for_location(publisher, topic, fetcher, schedule, last, shutdown.clone())
+    }))
+    .await;
+    flush(publisher).await;
+    results.into_iter().collect()
+}
 
+/// Publishes the hours strictly between `after` and `before`, one message per
+/// window to stay below the bus's message size limit.
+pub async fn publish_missing<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetcher: &F,
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+) -> Result<usize> {
+    let location = &fetcher.location().name;
+    let mut published = 0;
+    for window in plan_windows(
+        after.date_naive(),
+        before.date_naive(),
+        DEFAULT_WINDOW_DAYS,
+        Direction::Forward,
+    ) {
+        let start_date = window.start.format("%Y-%m-%d").to_string();
+        let end_date = window.end.format("%Y-%m-%d").to_string();
+        let missing: Vec<F::Record> = fetcher
+            .fetch_historical(&start_date, &end_date)
+            .await?
+            .into_iter()
+            .filter(|record| record.time() > after && record.time() < before)
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+        match send_batch(publisher, topic, location, &missing).await {
+            Ok(()) => published += missing.len(),
+            // Refetching a window the bus rejects for good would only fail again
+            Err(e) if !e.is_retryable() => {
+                error!(target: "producer",
+                    "[Producer] Dropped {} to {} for {}: {}", start_date, end_date, location, e
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(published)
+}
+
+/// Skips locations without any rows, which need a historical backfill first.
+pub async fn run_gap_fill<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetchers: &[F],
+    last_ingested: &HashMap<String, DateTime<Utc>>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let results = join_all(fetchers.iter().map(|fetcher| {
+        let mut shutdown = shutdown.clone();
+        async move {
+            let location = &fetcher.location().name;
+            let Some(&last) = last_ingested.get(location) else {
+                warn!(target: "producer",
+                    "[Producer] No data stored for {}, run a historical backfill first", location
+                );
+                return Ok(());
+            };
+
+            info!(target: "producer", "[Producer] Filling {} from {}", location, last);
+            let published = tokio::select! {
+                published = publish_missing(publisher, topic, fetcher, last, Utc::now()) => published,
+                _ = shutdown.triggered() => return Ok(()),
+            };
+            match published {
+                Ok(0) => info!(target: "producer", "[Producer] No gap for {}", location),
+                Ok(hours) => {
+                    info!(target: "producer", "[Producer] Published {} missing hours for {}", hours, location)
+                }
+                Err(ref e) => {
+                    error!(target: "producer", "[Producer] Failed to fill gap for {}: {}", location, e)
+                }
+            }
+            published.map(|_| ())
+        }
+    }))
+    .await;
+    flush(publisher).await;
+    info!(target: "producer", "[Producer] Gap fill complete");
+    results.into_iter().collect()
+}
+
+fn latest_hour<R: HourlyRecord>(hourly: &[R]) -> Option<DateTime<Utc>> {
+    hourly.iter().map(|r| r.time()).max()
+}
+
+/// Tokio timers stop while the machine is suspended, so this naps and
+/// re-checks the wall clock.
+async fn sleep_until_wall(deadline: DateTime<Utc>) {
     loop {
-        match fetcher.fetch_recent().await {
-            Ok(hourly) => match serde_json::to_string(&hourly) {
-                Ok(batch_payload) => {
-                    let record = FutureRecord::to("weather-data")
-                        .payload(&batch_payload)
-                        .key("key");
-                    info!(target: "producer", "[Producer] Sent : {:?}", batch_payload);
-                    match producer.send(record, Duration::from_secs(0)).await {
-                        Ok(delivery) => {
-                            info!(target: "producer", "[Producer] Delivered: {:?}", delivery)
-                        }
-                        Err((e, _)) => error!(target: "producer", "[Producer] Error: {:?}", e),
+        let Ok(remaining) = (deadline - Utc::now()).to_std() else {
+            return;
+        };
+        sleep(remaining.min(WALL_CLOCK_CHECK)).await;
+    }
+}
+
+async fn recent_for_location<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetcher: &F,
+    schedule: &Schedule,
+    last_ingested: Option<DateTime<Utc>>,
+    mut shutdown: Shutdown,
+) -> Result<()> {
+    let location = &fetcher.location().name;
+    info!(target: "producer",
+        "[Producer] Polling {} (tags: {:?})",
+        location, fetcher.location().tags
+    );
+
+    // Latest hour the bus has accepted, used to notice and fill hours
+    // lost to failed fetches or missed ticks. Seeding it with the latest
+    // stored hour makes the first fetch fill the gap since the last run.
+    let mut last_published: Option<DateTime<Utc>> = last_ingested;
+
+    while !shutdown.is_triggered() {
+        let fetched = tokio::select! {
+            fetched = fetcher.fetch_recent() => fetched,
+            _ = shutdown.triggered() => break,
+        };
+        match fetched {
+            Ok(hourly) => {
+                let latest = latest_hour(&hourly);
+                let mut caught_up = true;
+                // A replay would ask for windows that were never recorded
+                if let (Some(last), Some(latest), false) =
+                    (last_published, latest, fetcher.is_replay())
+                {
+                    if latest - last > TimeDuration::hours(1) {
+                        info!(target: "producer",
+                            "[Producer] Catching up {} from {} to {}", location, last, latest
+                        );
+                        caught_up =
+                            match publish_missing(publisher, topic, fetcher, last, latest).await {
+                                Ok(_) => true,
+                                Err(e) => {
+                                    error!(target: "producer",
+                                        "[Producer] Failed to catch up {}: {}", location, e
+                                    );
+                                    false
+                                }
+                            };
                     }
                 }
-                Err(e) => {
-                    error!(target: "producer", "Failed to serialize air quality batch: {}", e)
+                // A batch the bus rejects for good is not fetched again
+                let sent = match send_batch(publisher, topic, location, &hourly).await {
+                    Ok(()) => true,
+                    Err(e) => !e.is_retryable(),
+                };
+                if sent && caught_up {
+                    last_published = latest.or(last_published);
                 }
-            },
+            }
+            Err(Error::RecordingExhausted { .. }) => {
+                info!(target: "producer", "[Producer] Replayed every recorded response for {}", location);
+                break;
+            }
             Err(e) => {
                 error!(target: "producer",
-                    "[Producer] Failed to fetch air quality data for past hour {}",
-                    e
+                    "[Producer] Failed to fetch data for {} for past hour {}",
+                    location, e
                 );
             }
         }
-        sleep(Duration::from_secs(3600)).await;
+        if fetcher.is_replay() {
+            continue;
+        }
+
+        let next = schedule.next_after(Utc::now()).inspect_err(
+            |e| error!(target: "producer", "[Producer] Stopped polling {}: {}", location, e),
+        )?;
+        info!(target: "producer", "[Producer] Next fetch for {} at {}", location, next);
+        tokio::select! {
+            _ = sleep_until_wall(next) => {}
+            _ = shutdown.triggered() => break,
+        }
+
+        let missed = schedule.missed_between(next, Utc::now());
+        if missed > 0 {
+            warn!(target: "producer",
+                "[Producer] Woke late for {}, missed {} scheduled fetches", location, missed
+            );
+        }
+    }
+    info!(target: "producer", "[Producer] Stopped polling {}", location);
+    Ok(())
+}
+
+/// Nothing is caught up, since past forecasts cannot be fetched.
+pub async fn run_forecast_producer<F: DataFetcher + Sync, P: MessagePublisher + ?Sized>(
+    publisher: &P,
+    topic: &str,
+    fetchers: &[F],
+    schedule: &Schedule,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let results = join_all(fetchers.iter().map(|fetcher| {
+        let mut shutdown = shutdown.clone();
+        async move {
+            let location = &fetcher.location().name;
+            info!(target: "producer", "[Producer] Polling forecasts for {}", location);
+            while !shutdown.is_triggered() {
+                let fetched = tokio::select! {
+                    fetched = fetcher.fetch_recent() => fetched,
+                    _ = shutdown.triggered() => break,
+                };
+                match fetched {
+                    Ok(forecast) => {
+                        let _ = send_batch(publisher, topic, location, &forecast).await;
+                    }
+                    Err(Error::RecordingExhausted { .. }) => {
+                        info!(target: "producer", "[Producer] Replayed every recorded forecast for {}", location);
+                        break;
+                    }
+                    Err(e) => {
+                        error!(target: "producer",
+                            "[Producer] Failed to fetch forecast for {}: {}", location, e
+                        );
+                    }
+                }
+                if fetcher.is_replay() {
+                    continue;
+                }
+
+                let next = schedule.next_after(Utc::now()).inspect_err(|e| {
+                    error!(target: "producer",
+                        "[Producer] Stopped polling forecasts for {}: {}", location, e
+                    )
+                })?;
+                info!(target: "producer", "[Producer] Next forecast for {} at {}", location, next);
+                tokio::select! {
+                    _ = sleep_until_wall(next) => {}
+                    _ = shutdown.triggered() => break,
+                }
+            }
+            info!(target: "producer", "[Producer] Stopped polling forecasts for {}", location);
+            Ok(())
+        }
+    }))
+    .await;
+    flush(publisher).await;
+    results.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air_models::time_format::parse_date;
+    use crate::air_models::AirQualityHourly;
+    use crate::bus;
+    use crate::config::{Dataset, LocationConfig};
+    use crate::error::Error;
+    use crate::testing::{berlin, date, temp_dir};
+    use crate::traits::message_bus::MessageSubscriber;
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    /// Serves an empty reading for every hour of every requested day, except
+    /// for windows starting on `broken`, which get a malformed response.
+    struct EveryHour {
+        location: LocationConfig,
+        broken: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl DataFetcher for EveryHour {
+        type Record = AirQualityHourly;
+
+        fn location(&self) -> &LocationConfig {
+            &self.location
+        }
+
+        async fn fetch_historical(
+            &self,
+            start_date: &str,
+            end_date: &str,
+        ) -> Result<Vec<AirQualityHourly>> {
+            if self.broken == Some(start_date) {
+                return Err(Error::MisalignedColumn {
+                    variable: "pm10".to_string(),
+                    expected: 24,
+                    found: 23,
+                });
+            }
+            let start = parse_date(start_date)?
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc();
+            let end = parse_date(end_date)?
+                .and_hms_opt(23, 0, 0)
+                .unwrap()
+                .and_utc();
+            let mut hourly = Vec::new();
+            let mut time = start;
+            while time <= end {
+                hourly.push(serde_json::from_value(json!({
+                    "location": self.location.name,
+                    "time": time.to_rfc3339(),
+                }))?);
+                time += TimeDuration::hours(1);
+            }
+            Ok(hourly)
+        }
+
+        async fn fetch_recent(&self) -> Result<Vec<AirQualityHourly>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn every_hour() -> EveryHour {
+        EveryHour {
+            location: berlin(),
+            broken: None,
+        }
+    }
+
+    fn range(start: &str, end: &str) -> BackfillRange {
+        BackfillRange::new(
+            Dataset::AirQuality,
+            Some(date(start)),
+            Some(date(end)),
+            2,
+            Direction::Forward,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn long_gaps_are_published_one_window_at_a_time() {
+        let fetcher = every_hour();
+        let after = "2024-01-01T05:00:00Z".parse().unwrap();
+        let before = "2024-07-01T12:00:00Z".parse().unwrap();
+
+        let (publisher, mut subscriber) = bus::channel(16);
+        let published = publish_missing(&publisher, "air-quality", &fetcher, after, before)
+            .await
+            .unwrap();
+        drop(publisher);
+
+        let mut batches: Vec<Vec<AirQualityHourly>> = Vec::new();
+        while let Some(msg) = subscriber.next().await {
+            batches.push(serde_json::from_slice(&msg.unwrap().payload.unwrap()).unwrap());
+        }
+        // 183 days need two full windows and a short one
+        assert_eq!(batches.len(), 3);
+        assert!(batches
+            .iter()
+            .all(|batch| batch.len() <= DEFAULT_WINDOW_DAYS as usize * 24));
+        let hours: Vec<DateTime<Utc>> = batches.iter().flatten().map(|r| r.time).collect();
+        assert_eq!(published, hours.len());
+        assert_eq!(hours.first(), Some(&(after + TimeDuration::hours(1))));
+        assert_eq!(hours.last(), Some(&(before - TimeDuration::hours(1))));
+        assert!(hours
+            .windows(2)
+            .all(|w| w[1] - w[0] == TimeDuration::hours(1)));
+    }
+
+    #[tokio::test]
+    async fn backfill_fails_when_progress_cannot_be_saved() {
+        let dir = temp_dir("producer_checkpoint");
+        let checkpoints = CheckpointStore::load(&dir.join("historical.json")).unwrap();
+        // A file where the checkpoint directory should be
+        std::fs::write(&dir, "").unwrap();
+
+        let (publisher, _subscriber) = bus::channel(16);
+        let result = run_historical_producer(
+            &publisher,
+            "air-quality",
+            &[every_hour()],
+            &range("2024-03-01", "2024-03-04"),
+            Duration::ZERO,
+            &checkpoints,
+            &Shutdown::never(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Io(_))));
+
+        std::fs::remove_file(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn backfill_fails_with_the_error_of_a_dropped_window() {
+        let fetcher = EveryHour {
+            broken: Some("2024-03-03"),
+            ..every_hour()
+        };
+
+        let (publisher, mut subscriber) = bus::channel(16);
+        let result = run_historical_producer(
+            &publisher,
+            "air-quality",
+            &[fetcher],
+            &range("2024-03-01", "2024-03-06"),
+            Duration::ZERO,
+            &CheckpointStore::in_memory(),
+            &Shutdown::never(),
+        )
+        .await;
+        drop(publisher);
+
+        let e = result.unwrap_err();
+        assert!(matches!(e, Error::MisalignedColumn { .. }));
+        assert_eq!(e.exit_code(), 65);
+        // The windows around it were still published
+        let mut published = 0;
+        while subscriber.next().await.is_some() {
+            published += 1;
+        }
+        assert_eq!(published, 2);
+    }
+
+    #[tokio::test]
+    async fn gap_fill_fails_when_a_location_cannot_be_filled() {
+        let fetcher = EveryHour {
+            broken: Some("2024-03-01"),
+            ..every_hour()
+        };
+        let last_ingested = HashMap::from([(
+            "berlin".to_string(),
+            "2024-03-01T05:00:00Z".parse().unwrap(),
+        )]);
+
+        let (publisher, _subscriber) = bus::channel(16);
+        let result = run_gap_fill(
+            &publisher,
+            "air-quality",
+            &[fetcher],
+            &last_ingested,
+            &Shutdown::never(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::MisalignedColumn { .. })));
     }
 }
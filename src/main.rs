@@ -1,17 +1,58 @@
 use crate::{
-    air_models::APIFetcher,
-    config::{load_config, AppConfig},
-    kafka::{run_consumer, run_historical_producer, run_recent_producer},
+    air_models::{air_model::WELL_KNOWN_VARIABLES, APIFetcher, ForecastFetcher},
+    api_client::ApiClient,
+    backfill::{BackfillRange, Direction, DEFAULT_WINDOW_DAYS},
+    bus::{DirectPublisher, FileLogPublisher, FileLogSubscriber, KafkaPublisher, KafkaSubscriber},
+    checkpoint::CheckpointStore,
+    config::{load_config, AppConfig, DBConfig, Dataset},
+    db::{
+        aggregates, timescale,
+        verification::{self, Skill, VerifyFilter, Window},
+        MigrationState,
+    },
+    error::Result,
+    kafka::{
+        dlq::{replay_group_id, DeadLetterKind},
+        run_consumer, run_dlq_replay, run_forecast_producer, run_gap_fill, run_historical_producer,
+        run_recent_producer,
+    },
     logging::setup_logging,
+    replay::{FileFetcher, Recorded},
+    schedule::Schedule,
+    shutdown::Shutdown,
+    traits::{
+        data_fetcher::DataFetcher,
+        data_loader::Persistable,
+        message_bus::{MessagePublisher, MessageSubscriber},
+    },
+    weather_models::WeatherFetcher,
 };
-use clap::{Parser, Subcommand, ValueEnum};
-use reqwest::Client;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 mod air_models;
+mod api_client;
+mod backfill;
+mod bus;
+mod checkpoint;
 mod config;
+mod db;
+mod error;
 mod kafka;
 mod logging;
+mod replay;
+mod schedule;
+mod shutdown;
+#[cfg(test)]
+mod testing;
 mod traits;
-use tracing::info;
+mod weather_models;
+use tracing::{error, info};
+
+/// Batches the pipeline producer may queue ahead of the consumer.
+const PIPELINE_CAPACITY: usize = 16;
 
 /// Kafka CLI App
 #[derive(Parser)]
@@ -25,6 +66,22 @@ struct Cli {
     #[arg(short, long, default_value = "localhost:9092")]
     broker: String,
 
+    /// Message bus connecting producers and consumers
+    #[arg(long, value_enum, default_value = "kafka")]
+    bus: BusKind,
+
+    /// Directory holding the topic logs when using the file bus
+    #[arg(long, default_value = "bus")]
+    bus_dir: PathBuf,
+
+    /// Data fetched, published and stored by the command
+    #[arg(long, value_enum, default_value = "air-quality")]
+    dataset: Dataset,
+
+    /// Seconds allowed for finishing in-flight work after SIGINT/SIGTERM
+    #[arg(long, default_value_t = 8)]
+    shutdown_timeout: u64,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,47 +89,704 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run the Kafka producer
-    Producer {
-        #[arg(short, long, default_value = "recent")]
-        mode: ProducerMode,
-    },
+    Producer(ProducerArgs),
 
     /// Run the Kafka consumer
     Consumer,
+
+    /// Run a producer and the consumer in one process, connected by an
+    /// in-memory channel instead of a broker
+    Pipeline(ProducerArgs),
+
+    /// Fetch and write straight to the database, without any message bus
+    Direct(ProducerArgs),
+
+    /// Work with the dead-letter topic
+    Dlq {
+        #[command(subcommand)]
+        command: DlqCommand,
+    },
+
+    /// Recompute the daily and weekly rollups, e.g. after a historical load
+    RefreshAggregates {
+        /// First day to refresh, from the earliest stored hour if not given
+        #[arg(long)]
+        start: Option<NaiveDate>,
+
+        /// Day to refresh up to, exclusive, up to the latest stored hour if not given
+        #[arg(long)]
+        end: Option<NaiveDate>,
+    },
+
+    /// Score stored forecasts against the observed readings
+    Verify {
+        /// First forecast day to score, from the earliest stored one if not given
+        #[arg(long)]
+        start: Option<NaiveDate>,
+
+        /// Day to score up to, exclusive, up to the latest stored one if not given
+        #[arg(long)]
+        end: Option<NaiveDate>,
+
+        /// Only score this location, may be repeated
+        #[arg(long)]
+        location: Vec<String>,
+
+        /// Only score this pollutant, may be repeated
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(WELL_KNOWN_VARIABLES))]
+        pollutant: Vec<String>,
+
+        /// Lead hours grouped into one score, 24 scores each forecast day
+        #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(i32).range(1..))]
+        lead_step: i32,
+
+        /// Period the scores are grouped by
+        #[arg(long, value_enum, default_value = "all")]
+        window: Window,
+
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Manage the database schema
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Apply pending schema migrations
+    Migrate,
+    /// List migrations and whether they have been applied
+    Status,
+    /// Show the hypertables' chunk, compression and retention settings
+    Policies,
+}
+
+#[derive(Args, Clone)]
+struct ProducerArgs {
+    #[arg(short, long, default_value = "recent")]
+    mode: ProducerMode,
+
+    /// File recording completed historical windows, used to resume a backfill
+    /// [default: checkpoints/historical.json, or
+    /// checkpoints/weather-historical.json for weather]
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Discard recorded checkpoints and start the backfill over
+    #[arg(long)]
+    reset: bool,
+
+    /// First day of the historical backfill, e.g. 2024-03-01
+    #[arg(long)]
+    start: Option<NaiveDate>,
+
+    /// Last day of the historical backfill, defaults to today
+    #[arg(long)]
+    end: Option<NaiveDate>,
+
+    /// Number of days requested from the API per call
+    #[arg(long, default_value_t = DEFAULT_WINDOW_DAYS)]
+    window_days: i64,
+
+    /// Order in which the historical windows are fetched
+    #[arg(long, value_enum, default_value = "backward")]
+    direction: Direction,
+
+    /// Seconds to wait between historical requests
+    #[arg(long, default_value_t = 5)]
+    pause_secs: u64,
+
+    /// In recent mode, first publish the hours missing since the last stored reading
+    #[arg(long)]
+    fill_gaps: bool,
+
+    /// Save every API response under this directory
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Replay the responses saved with --record in this directory instead of
+    /// calling the API
+    #[arg(long, conflicts_with_all = ["record", "checkpoint", "reset", "fill_gaps"])]
+    replay: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum DlqCommand {
+    /// Send dead-lettered messages back to the main topic
+    Replay {
+        /// Only replay messages that failed with this kind of error
+        #[arg(long, value_enum)]
+        kind: Option<DeadLetterKind>,
+
+        /// Stop after this many seconds without a new message
+        #[arg(long, default_value_t = 10)]
+        idle_timeout_secs: u64,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum BusKind {
+    Kafka,
+    /// Append-only JSON-lines files under `--bus-dir`
+    File,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum OutputFormat {
+    Table,
+    Csv,
+    Json,
 }
 
 #[derive(ValueEnum, Clone)]
 enum ProducerMode {
     Historical,
     Recent,
+    /// Publish the hours missing since the last stored reading, then exit
+    GapFill,
+    /// Publish the air quality forecast on the recent schedule, always with
+    /// the forecast data set
+    Forecast,
 }
 
 #[tokio::main]
 async fn main() {
-    let config: AppConfig = load_config().expect("Failed to load config");
-    let fetcher = APIFetcher {
-        client: Client::new(),
-        latitude: config.location.latitude,
-        longitude: config.location.longitude,
-    };
-
     let cli = Cli::parse();
     setup_logging();
 
-    match cli.command {
-        Commands::Producer { mode } => match mode {
-            ProducerMode::Historical => {
-                info!(target: "producer", "Starting Historical Producer. Listening...");
-                run_historical_producer(&cli.broker, &fetcher).await;
+    if let Err(e) = run(cli).await {
+        error!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn last_ingested(
+    config: &AppConfig,
+    dataset: Dataset,
+) -> Result<HashMap<String, DateTime<Utc>>> {
+    let pool = db::connect(&config.database.db_url).await?;
+    let names: Vec<String> = config.locations.iter().map(|l| l.name.clone()).collect();
+    let last = db::last_ingested(&pool, dataset.table(), &names).await?;
+    pool.close().await;
+    Ok(last)
+}
+
+fn publisher(cli: &Cli) -> Result<Box<dyn MessagePublisher>> {
+    Ok(match cli.bus {
+        BusKind::Kafka => Box::new(KafkaPublisher::new(
+            &cli.broker,
+            Duration::from_secs(cli.shutdown_timeout),
+        )?),
+        BusKind::File => Box::new(FileLogPublisher::new(&cli.bus_dir)),
+    })
+}
+
+fn subscriber(cli: &Cli, group_id: &str, topic: &str) -> Result<Box<dyn MessageSubscriber>> {
+    Ok(match cli.bus {
+        BusKind::Kafka => Box::new(KafkaSubscriber::new(&cli.broker, group_id, &[topic])?),
+        BusKind::File => Box::new(FileLogSubscriber::new(&cli.bus_dir, group_id, topic)?),
+    })
+}
+
+async fn run_producer<F: DataFetcher + Sync>(
+    args: ProducerArgs,
+    dataset: Dataset,
+    config: &AppConfig,
+    fetchers: &[F],
+    publisher: &dyn MessagePublisher,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let topic = config.kafka.topic_for(dataset);
+    match args.mode {
+        ProducerMode::Historical => {
+            let range = BackfillRange::new(
+                dataset,
+                args.start,
+                args.end,
+                args.window_days,
+                args.direction,
+            )
+            .unwrap_or_else(|e| Cli::command().error(ErrorKind::ValueValidation, e).exit());
+            // The recording run already checked off every window it fetched,
+            // so a replay keeps its progress to itself.
+            let checkpoints = if args.replay.is_some() {
+                CheckpointStore::in_memory()
+            } else {
+                let checkpoint = args.checkpoint.unwrap_or_else(|| match dataset {
+                    Dataset::Weather => PathBuf::from("checkpoints/weather-historical.json"),
+                    Dataset::AirQuality | Dataset::Forecast => {
+                        PathBuf::from("checkpoints/historical.json")
+                    }
+                });
+                if args.reset {
+                    CheckpointStore::reset(&checkpoint)?;
+                    info!(target: "producer", "Discarded checkpoints in {}", checkpoint.display());
+                }
+                CheckpointStore::load(&checkpoint)?
+            };
+            // Recorded responses are not rate limited
+            let pause = if args.replay.is_some() {
+                Duration::ZERO
+            } else {
+                Duration::from_secs(args.pause_secs)
+            };
+            info!(target: "producer", "Starting Historical Producer. Listening...");
+            run_historical_producer(
+                publisher,
+                topic,
+                fetchers,
+                &range,
+                pause,
+                &checkpoints,
+                shutdown,
+            )
+            .await
+        }
+        ProducerMode::Recent => {
+            let schedule = Schedule::from_config(&config.schedule)?;
+            let last_ingested = if args.fill_gaps {
+                last_ingested(config, dataset).await?
+            } else {
+                HashMap::new()
+            };
+            info!(target: "producer", "Starting Recent Producer. Listening...");
+            run_recent_producer(
+                publisher,
+                topic,
+                fetchers,
+                &schedule,
+                &last_ingested,
+                shutdown,
+            )
+            .await
+        }
+        ProducerMode::GapFill => {
+            let last_ingested = last_ingested(config, dataset).await?;
+            info!(target: "producer", "Starting Gap Fill Producer...");
+            run_gap_fill(publisher, topic, fetchers, &last_ingested, shutdown).await
+        }
+        ProducerMode::Forecast => {
+            let schedule = Schedule::from_config(&config.schedule)?;
+            info!(target: "producer", "Starting Forecast Producer. Listening...");
+            run_forecast_producer(publisher, topic, fetchers, &schedule, shutdown).await
+        }
+    }
+}
+
+fn producer_args(cli: &Cli) -> Option<&ProducerArgs> {
+    match &cli.command {
+        Commands::Producer(args) | Commands::Pipeline(args) | Commands::Direct(args) => Some(args),
+        _ => None,
+    }
+}
+
+/// Exits when the options conflict with `--mode`.
+fn dataset(cli: &Cli) -> Dataset {
+    let args = producer_args(cli);
+    if args.is_some_and(|args| args.replay.is_some() && matches!(args.mode, ProducerMode::GapFill))
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--replay cannot be used with --mode gap-fill",
+            )
+            .exit()
+    }
+    dataset_for_mode(cli.dataset, args.map(|args| &args.mode))
+}
+
+fn dataset_for_mode(dataset: Dataset, mode: Option<&ProducerMode>) -> Dataset {
+    match (dataset, mode) {
+        (Dataset::Weather, Some(ProducerMode::Forecast)) => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--mode forecast is only available for air quality",
+            )
+            .exit(),
+        (_, Some(ProducerMode::Forecast)) => Dataset::Forecast,
+        (Dataset::Forecast, Some(_)) => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "forecasts can only be produced with --mode forecast",
+            )
+            .exit(),
+        (dataset, _) => dataset,
+    }
+}
+
+async fn check_schema(config: &AppConfig) -> Result<()> {
+    if !config.database.require_current_schema {
+        return Ok(());
+    }
+    let pool = db::connect(&config.database.db_url).await?;
+    let result = db::ensure_schema_current(&pool).await;
+    pool.close().await;
+    result
+}
+
+fn print_policies(table: &str, policies: &timescale::Policies, db: &DBConfig) {
+    let Some(chunk_interval) = &policies.chunk_interval else {
+        println!("{} is not a hypertable yet, run `db migrate`", table);
+        return;
+    };
+    let compression = if policies.compression_enabled {
+        format!(
+            "segmented by {}, ordered by {}",
+            policies.segment_by.join(", "),
+            policies.order_by.join(", ")
+        )
+    } else {
+        "off".to_string()
+    };
+    let configured_compression = if db.compression {
+        db.compress_after.as_str()
+    } else {
+        "off"
+    };
+    println!("{:<16}{:<40}configured", table, "current");
+    println!(
+        "{:<16}{:<40}{}",
+        "chunk interval", chunk_interval, db.chunk_interval
+    );
+    println!("{:<16}{:<40}", "compression", compression);
+    println!(
+        "{:<16}{:<40}{}",
+        "compress after",
+        policies.compress_after.as_deref().unwrap_or("never"),
+        configured_compression
+    );
+    println!(
+        "{:<16}{:<40}{}",
+        "retention",
+        policies.retention.as_deref().unwrap_or("forever"),
+        db.retention.as_deref().unwrap_or("forever")
+    );
+}
+
+fn print_verification(skills: &[Skill], format: OutputFormat) -> Result<()> {
+    let score = |value: Option<f64>| value.map(|v| format!("{:.3}", v)).unwrap_or_default();
+    let window = |skill: &Skill| {
+        skill
+            .window_start
+            .map(|start| start.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "all".to_string())
+    };
+    match format {
+        OutputFormat::Table => {
+            if skills.is_empty() {
+                println!("No forecasts with matching observations");
+                return Ok(());
+            }
+            println!(
+                "{:<16}{:<24}{:>9}  {:<12}{:>8}{:>10}{:>10}{:>10}{:>8}",
+                "location", "pollutant", "lead", "window", "n", "bias", "mae", "rmse", "corr"
+            );
+            for skill in skills {
+                println!(
+                    "{:<16}{:<24}{:>9}  {:<12}{:>8}{:>10}{:>10}{:>10}{:>8}",
+                    skill.location,
+                    skill.pollutant,
+                    format!("{}-{}h", skill.lead_from, skill.lead_to),
+                    window(skill),
+                    skill.samples,
+                    score(skill.bias),
+                    score(skill.mae),
+                    score(skill.rmse),
+                    score(skill.correlation)
+                );
+            }
+        }
+        OutputFormat::Csv => {
+            println!("location,pollutant,lead_from,lead_to,window_start,samples,bias,mae,rmse,correlation");
+            for skill in skills {
+                // Location names are the only free text
+                let location = if skill.location.contains([',', '"', '\n']) {
+                    format!("\"{}\"", skill.location.replace('"', "\"\""))
+                } else {
+                    skill.location.clone()
+                };
+                println!(
+                    "{},{},{},{},{},{},{},{},{},{}",
+                    location,
+                    skill.pollutant,
+                    skill.lead_from,
+                    skill.lead_to,
+                    skill
+                        .window_start
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_default(),
+                    skill.samples,
+                    skill.bias.map(|v| v.to_string()).unwrap_or_default(),
+                    skill.mae.map(|v| v.to_string()).unwrap_or_default(),
+                    skill.rmse.map(|v| v.to_string()).unwrap_or_default(),
+                    skill.correlation.map(|v| v.to_string()).unwrap_or_default()
+                );
             }
-            ProducerMode::Recent => {
-                info!(target: "producer", "Starting Recent Producer. Listening...");
-                run_recent_producer(&cli.broker, &fetcher).await;
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(skills)?),
+    }
+    Ok(())
+}
+
+async fn run_db(command: DbCommand, config: &AppConfig) -> Result<()> {
+    let pool = db::connect(&config.database.db_url).await?;
+    let result = match command {
+        DbCommand::Migrate => {
+            info!("Applying migrations");
+            match db::migrate(&pool).await {
+                Ok(()) => {
+                    info!("Applying hypertable policies");
+                    timescale::apply_policies(&pool, &config.database).await
+                }
+                Err(e) => Err(e),
             }
-        },
+        }
+        DbCommand::Status => db::migration_status(&pool).await.map(|migrations| {
+            for m in migrations {
+                let state = match m.state {
+                    MigrationState::Applied => "applied",
+                    MigrationState::Pending => "pending",
+                    MigrationState::Changed => "changed since applied",
+                };
+                println!("{:>4}  {:<30} {}", m.version, m.description, state);
+            }
+        }),
+        DbCommand::Policies => {
+            let mut result = Ok(());
+            for (i, hypertable) in timescale::HYPERTABLES.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                match timescale::current_policies(&pool, hypertable.name).await {
+                    Ok(policies) => print_policies(hypertable.name, &policies, &config.database),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            result
+        }
+    };
+    pool.close().await;
+    result
+}
+
+async fn run(mut cli: Cli) -> Result<()> {
+    cli.dataset = dataset(&cli);
+    let config: AppConfig = load_config()?;
+    let shutdown = Shutdown::listen(Duration::from_secs(cli.shutdown_timeout))?;
+    let mut http = ApiClient::new(config.api.clone())?;
+    http.record = producer_args(&cli).and_then(|args| args.record.clone());
+    let replay = producer_args(&cli).and_then(|args| args.replay.clone());
+    match (cli.dataset, replay) {
+        (Dataset::AirQuality, Some(dir)) => {
+            let fetchers = file_fetchers::<APIFetcher>(&dir, &config)?;
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+        (Dataset::Weather, Some(dir)) => {
+            let fetchers = file_fetchers::<WeatherFetcher>(&dir, &config)?;
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+        (Dataset::Forecast, Some(dir)) => {
+            let fetchers = file_fetchers::<ForecastFetcher>(&dir, &config)?;
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+        (Dataset::AirQuality, None) => {
+            let fetchers: Vec<APIFetcher> = config
+                .locations
+                .iter()
+                .map(|location| APIFetcher {
+                    http: http.clone(),
+                    location: location.clone(),
+                })
+                .collect();
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+        (Dataset::Weather, None) => {
+            let fetchers: Vec<WeatherFetcher> = config
+                .locations
+                .iter()
+                .map(|location| WeatherFetcher {
+                    http: http.clone(),
+                    location: location.clone(),
+                })
+                .collect();
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+        (Dataset::Forecast, None) => {
+            let fetchers: Vec<ForecastFetcher> = config
+                .locations
+                .iter()
+                .map(|location| ForecastFetcher {
+                    fetcher: APIFetcher {
+                        http: http.clone(),
+                        location: location.clone(),
+                    },
+                })
+                .collect();
+            run_command(cli, &config, &fetchers, shutdown).await
+        }
+    }
+}
+
+/// Fetchers serving the responses `F` recorded under `dir`, one per location.
+fn file_fetchers<F: Recorded>(dir: &Path, config: &AppConfig) -> Result<Vec<FileFetcher<F>>> {
+    info!(target: "producer", "Replaying responses recorded in {}", dir.display());
+    config
+        .locations
+        .iter()
+        .map(|location| FileFetcher::new(dir, location.clone()))
+        .collect()
+}
+
+async fn run_command<F>(
+    cli: Cli,
+    config: &AppConfig,
+    fetchers: &[F],
+    shutdown: Shutdown,
+) -> Result<()>
+where
+    F: DataFetcher + Sync,
+    Vec<F::Record>: Persistable,
+{
+    let dataset = cli.dataset;
+    let kafka = &config.kafka;
+    match cli.command {
+        Commands::Producer(ref args) => {
+            let publisher = publisher(&cli)?;
+            run_producer(
+                args.clone(),
+                dataset,
+                config,
+                fetchers,
+                publisher.as_ref(),
+                &shutdown,
+            )
+            .await
+        }
         Commands::Consumer => {
+            check_schema(config).await?;
+            let mut subscriber =
+                subscriber(&cli, &kafka.group_id_for(dataset), kafka.topic_for(dataset))?;
+            let dlq = publisher(&cli)?;
+            let pool = db::connect(&config.database.db_url).await?;
             info!(target: "consumer", "Starting Consumer. Listening...");
-            run_consumer(&cli.broker, config.database.db_url.as_str()).await;
+            let result = run_consumer::<F::Record, _, _>(
+                subscriber.as_mut(),
+                dlq.as_ref(),
+                &pool,
+                kafka.dlq_topic_for(dataset),
+                config.database.on_conflict,
+                &config.consumer,
+                shutdown,
+            )
+            .await;
+            pool.close().await;
+            result
+        }
+        Commands::Pipeline(args) => {
+            check_schema(config).await?;
+            let (publisher, mut subscriber) = bus::channel(PIPELINE_CAPACITY);
+            // Dead letters must outlive the process, so they go to the file
+            // log rather than the channel.
+            let dlq = FileLogPublisher::new(&cli.bus_dir);
+            let pool = db::connect(&config.database.db_url).await?;
+            info!(target: "producer", "Starting Pipeline...");
+
+            let producer = async {
+                let result =
+                    run_producer(args, dataset, config, fetchers, &publisher, &shutdown).await;
+                // Closing the channel lets the consumer finish what is queued
+                drop(publisher);
+                result
+            };
+            // The consumer stops once the producer is done instead of on the
+            // signal, so batches already fetched still get stored.
+            let consumer = run_consumer::<F::Record, _, _>(
+                &mut subscriber,
+                &dlq,
+                &pool,
+                kafka.dlq_topic_for(dataset),
+                config.database.on_conflict,
+                &config.consumer,
+                Shutdown::never(),
+            );
+            // If either side fails the other is dropped, since a consumer
+            // that stopped would otherwise leave the producer blocked.
+            let result = tokio::try_join!(producer, consumer).map(|_| ());
+            pool.close().await;
+            result
+        }
+        Commands::Direct(args) => {
+            check_schema(config).await?;
+            let publisher = DirectPublisher::<F::Record>::connect(
+                &config.database.db_url,
+                config.database.on_conflict,
+                config.consumer.clone(),
+                shutdown.clone(),
+            )
+            .await?;
+            info!(target: "producer", "Starting Direct Mode...");
+            let result = run_producer(args, dataset, config, fetchers, &publisher, &shutdown).await;
+            publisher.close().await;
+            result
+        }
+        Commands::Db { command } => run_db(command, config).await,
+        Commands::RefreshAggregates { start, end } => {
+            let pool = db::connect(&config.database.db_url).await?;
+            let result = aggregates::refresh_aggregates(&pool, start, end).await;
+            pool.close().await;
+            result
+        }
+        Commands::Verify {
+            start,
+            end,
+            location,
+            pollutant,
+            lead_step,
+            window,
+            format,
+        } => {
+            let filter = VerifyFilter {
+                start,
+                end,
+                locations: location,
+                pollutants: pollutant,
+                lead_step,
+                window,
+            };
+            let pool = db::connect(&config.database.db_url).await?;
+            let result = verification::verify(&pool, &filter).await;
+            pool.close().await;
+            print_verification(&result?, format)
+        }
+        Commands::Dlq {
+            command:
+                DlqCommand::Replay {
+                    kind,
+                    idle_timeout_secs,
+                },
+        } => {
+            let mut subscriber = subscriber(
+                &cli,
+                &replay_group_id(&kafka.group_id_for(dataset), kind),
+                kafka.dlq_topic_for(dataset),
+            )?;
+            let publisher = publisher(&cli)?;
+            info!(target: "consumer", "Replaying messages from {}", kafka.dlq_topic_for(dataset));
+            run_dlq_replay(
+                subscriber.as_mut(),
+                publisher.as_ref(),
+                kafka.topic_for(dataset),
+                kind,
+                Duration::from_secs(idle_timeout_secs),
+            )
+            .await
         }
     }
 }
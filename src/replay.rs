@@ -0,0 +1,430 @@
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::air_models::time_format::parse_date;
+use crate::api_client::RECORDED_AT_FORMAT;
+use crate::config::LocationConfig;
+use crate::error::{Error, Result};
+use crate::traits::data_fetcher::{DataFetcher, HourlyRecord};
+
+/// How a fetcher names its recorded responses and turns them into records,
+/// shared by the live fetcher and [`FileFetcher`].
+pub trait Recorded {
+    type Raw: DeserializeOwned;
+    type Record: HourlyRecord;
+
+    /// Prefix of recorded recent responses, followed by when they were fetched
+    const RECENT_PREFIX: &'static str;
+    /// Prefixes of recorded historical responses, followed by the first and
+    /// last day they cover
+    const HISTORICAL_PREFIXES: &'static [&'static str];
+
+    /// What a recent fetch at `fetched_at` keeps of a response.
+    fn recent(
+        raw: Self::Raw,
+        location: &str,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<Vec<Self::Record>>;
+
+    /// Every reading of a historical response.
+    fn historical(raw: Self::Raw, location: &str) -> Result<Vec<Self::Record>>;
+}
+
+/// Name a recent response fetched at `at` is recorded under.
+pub fn recent_name(prefix: &str, at: DateTime<Utc>) -> String {
+    format!("{}{}", prefix, at.format(RECORDED_AT_FORMAT))
+}
+
+/// Name a historical response for `start..=end` is recorded under.
+pub fn historical_name(prefix: &str, start: impl ToString, end: impl ToString) -> String {
+    format!("{}{}_{}", prefix, start.to_string(), end.to_string())
+}
+
+/// The first and last day in what follows a historical prefix.
+fn recorded_days(days: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (start, end) = days.split_once('_')?;
+    Some((parse_date(start).ok()?, parse_date(end).ok()?))
+}
+
+/// Serves the responses `F` recorded with `--record`. Each recent fetch takes
+/// the oldest one left.
+pub struct FileFetcher<F> {
+    pub location: LocationConfig,
+    /// Recent responses not replayed yet, with when they were fetched
+    recent: Mutex<VecDeque<(DateTime<Utc>, PathBuf)>>,
+    /// Historical responses with the first and last day they cover
+    historical: Vec<(NaiveDate, NaiveDate, PathBuf)>,
+    fetcher: PhantomData<fn() -> F>,
+}
+
+impl<F: Recorded> FileFetcher<F> {
+    pub fn new(dir: &Path, location: LocationConfig) -> Result<Self> {
+        let mut recent = Vec::new();
+        let mut historical = Vec::new();
+        let location_dir = dir.join(&location.name);
+        if location_dir.is_dir() {
+            for entry in fs::read_dir(&location_dir)? {
+                let path = entry?.path();
+                let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                if let Some(fetched_at) = stem
+                    .strip_prefix(F::RECENT_PREFIX)
+                    .and_then(|at| NaiveDateTime::parse_from_str(at, RECORDED_AT_FORMAT).ok())
+                {
+                    recent.push((fetched_at.and_utc(), path));
+                } else if let Some((start, end)) = F::HISTORICAL_PREFIXES
+                    .iter()
+                    .find_map(|prefix| recorded_days(stem.strip_prefix(prefix)?))
+                {
+                    historical.push((start, end, path));
+                }
+            }
+        }
+        recent.sort();
+        historical.sort();
+
+        Ok(FileFetcher {
+            location,
+            recent: Mutex::new(recent.into()),
+            historical,
+            fetcher: PhantomData,
+        })
+    }
+
+    async fn read(&self, path: &Path) -> Result<F::Raw> {
+        Ok(serde_json::from_str(
+            &tokio::fs::read_to_string(path).await?,
+        )?)
+    }
+}
+
+#[async_trait]
+impl<F: Recorded> DataFetcher for FileFetcher<F> {
+    type Record = F::Record;
+
+    fn location(&self) -> &LocationConfig {
+        &self.location
+    }
+
+    fn is_replay(&self) -> bool {
+        true
+    }
+
+    async fn fetch_recent(&self) -> Result<Vec<F::Record>> {
+        let next = self
+            .recent
+            .lock()
+            .expect("recent queue poisoned")
+            .pop_front();
+        let Some((fetched_at, path)) = next else {
+            return Err(Error::RecordingExhausted {
+                location: self.location.name.clone(),
+            });
+        };
+        F::recent(self.read(&path).await?, &self.location.name, fetched_at)
+    }
+
+    async fn fetch_historical(&self, start_date: &str, end_date: &str) -> Result<Vec<F::Record>> {
+        let (start, end) = (parse_date(start_date)?, parse_date(end_date)?);
+        let recorded: Vec<&PathBuf> = self
+            .historical
+            .iter()
+            .filter(|(first, last, _)| *first >= start && *last <= end)
+            .map(|(_, _, path)| path)
+            .collect();
+        if recorded.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no recorded response for {} from {} to {}",
+                    self.location.name, start_date, end_date
+                ),
+            )
+            .into());
+        }
+
+        let mut hourly = Vec::new();
+        for path in recorded {
+            hourly.extend(F::historical(self.read(path).await?, &self.location.name)?);
+        }
+        Ok(hourly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air_models::{APIFetcher, AirQualityHourly, ForecastFetcher};
+    use crate::api_client::recording_path;
+    use crate::api_client::tests::serve;
+    use crate::backfill::{BackfillRange, Direction};
+    use crate::bus;
+    use crate::checkpoint::CheckpointStore;
+    use crate::config::Dataset;
+    use crate::kafka::{run_historical_producer, run_recent_producer};
+    use crate::schedule::Schedule;
+    use crate::shutdown::Shutdown;
+    use crate::testing::{berlin, temp_dir};
+    use crate::traits::message_bus::MessageSubscriber;
+    use crate::weather_models::WeatherFetcher;
+    use chrono::{NaiveDate, TimeDelta};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn hour(at: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%dT%H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    /// An Open-Meteo response with `hours` hourly values of `variable` from
+    /// `first`, each value being the hour of the day.
+    fn response(first: &str, hours: i64, variable: &str) -> String {
+        let times: Vec<DateTime<Utc>> = (0..hours)
+            .map(|i| hour(first) + TimeDelta::hours(i))
+            .collect();
+        let values: Vec<f64> = times
+            .iter()
+            .map(|t| t.format("%H").to_string().parse().unwrap())
+            .collect();
+        json!({
+            "latitude": 52.52,
+            "longitude": 13.41,
+            "elevation": 38.0,
+            "generationtime_ms": 0.1,
+            "utc_offset_seconds": 0,
+            "timezone": "GMT",
+            "timezone_abbreviation": "GMT",
+            "hourly": {
+                "time": times.iter().map(|t| t.format("%Y-%m-%dT%H:%M").to_string()).collect::<Vec<_>>(),
+                variable: values,
+            },
+        })
+        .to_string()
+    }
+
+    fn record(dir: &Path, name: &str, first: &str, hours: i64, variable: &str) {
+        let path = recording_path(dir, "berlin", name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, response(first, hours, variable)).unwrap();
+    }
+
+    /// Everything published, in order, once the publisher is gone.
+    async fn published(
+        mut subscriber: bus::channel::ChannelSubscriber,
+    ) -> Vec<Vec<AirQualityHourly>> {
+        let mut batches = Vec::new();
+        while let Some(msg) = subscriber.next().await {
+            let payload = msg.unwrap().payload.unwrap();
+            batches.push(serde_json::from_slice(&payload).unwrap());
+        }
+        batches
+    }
+
+    #[tokio::test]
+    async fn replays_a_recorded_day_without_waiting_for_the_schedule() {
+        let dir = temp_dir("replay_recent");
+        for fetched_at in ["20240301T100500Z", "20240301T110500Z", "20240301T120500Z"] {
+            let name = format!("{}{}", APIFetcher::RECENT_PREFIX, fetched_at);
+            // Each response also has hours that were still ahead when fetched
+            record(&dir, &name, "2024-03-01T09:00", 5, "pm10");
+        }
+        let fetchers = [FileFetcher::<APIFetcher>::new(&dir, berlin()).unwrap()];
+
+        let (publisher, subscriber) = bus::channel(16);
+        let schedule = Schedule::Hourly {
+            offset: TimeDelta::minutes(5),
+        };
+        let shutdown = Shutdown::never();
+        let last_ingested = HashMap::new();
+        let run = run_recent_producer(
+            &publisher,
+            "air-quality",
+            &fetchers,
+            &schedule,
+            &last_ingested,
+            &shutdown,
+        );
+        tokio::time::timeout(Duration::from_secs(5), run)
+            .await
+            .expect("replay waited on the schedule or never ran out")
+            .unwrap();
+        drop(publisher);
+
+        let batches = published(subscriber).await;
+        let times: Vec<_> = batches.iter().flatten().map(|r| r.time).collect();
+        assert_eq!(
+            times,
+            [
+                hour("2024-03-01T10:00"),
+                hour("2024-03-01T11:00"),
+                hour("2024-03-01T12:00")
+            ]
+        );
+        assert!(batches.iter().flatten().all(|r| r.location == "berlin"));
+        assert_eq!(batches[0][0].pm10, Some(10.0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_a_backfill_from_recorded_windows() {
+        let dir = temp_dir("replay_historical");
+        record(
+            &dir,
+            "air-quality_2024-02-26_2024-02-27",
+            "2024-02-26T00:00",
+            48,
+            "pm10",
+        );
+        record(
+            &dir,
+            "air-quality_2024-02-28_2024-02-29",
+            "2024-02-28T00:00",
+            48,
+            "pm10",
+        );
+        // Neither a recent response nor a forecast is part of the backfill
+        record(
+            &dir,
+            "air-quality_recent_20240228T100500Z",
+            "2024-02-28T09:00",
+            2,
+            "pm10",
+        );
+        record(
+            &dir,
+            "air-quality_forecast_20240228T100000Z",
+            "2024-02-28T10:00",
+            2,
+            "pm10",
+        );
+        let fetchers = [FileFetcher::<APIFetcher>::new(&dir, berlin()).unwrap()];
+
+        let range = BackfillRange::new(
+            Dataset::AirQuality,
+            Some(NaiveDate::from_ymd_opt(2024, 2, 26).unwrap()),
+            Some(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()),
+            2,
+            Direction::Forward,
+        )
+        .unwrap();
+        let (publisher, subscriber) = bus::channel(16);
+        run_historical_producer(
+            &publisher,
+            "air-quality",
+            &fetchers,
+            &range,
+            Duration::ZERO,
+            &CheckpointStore::in_memory(),
+            &Shutdown::never(),
+        )
+        .await
+        .unwrap();
+        drop(publisher);
+
+        let batches = published(subscriber).await;
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 48);
+        assert_eq!(batches[0][0].time, hour("2024-02-26T00:00"));
+        assert_eq!(batches[1][47].time, hour("2024-02-29T23:00"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_weather_split_between_archive_and_forecast() {
+        let dir = temp_dir("replay_weather");
+        record(
+            &dir,
+            "weather_archive_2024-03-01_2024-03-02",
+            "2024-03-01T00:00",
+            48,
+            "temperature_2m",
+        );
+        record(
+            &dir,
+            "weather_forecast_2024-03-03_2024-03-03",
+            "2024-03-03T00:00",
+            24,
+            "temperature_2m",
+        );
+        let fetcher = FileFetcher::<WeatherFetcher>::new(&dir, berlin()).unwrap();
+
+        let hourly = fetcher
+            .fetch_historical("2024-03-01", "2024-03-03")
+            .await
+            .unwrap();
+        assert_eq!(hourly.len(), 72);
+        assert_eq!(hourly[0].time, hour("2024-03-01T00:00"));
+        assert_eq!(hourly[71].time, hour("2024-03-03T23:00"));
+        assert_eq!(hourly[71].temperature_2m, Some(23.0));
+
+        let missing = fetcher.fetch_historical("2024-03-04", "2024-03-05").await;
+        assert!(matches!(missing, Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_forecasts_until_the_recording_runs_out() {
+        let dir = temp_dir("replay_forecast");
+        record(
+            &dir,
+            "air-quality_forecast_20240301T100000Z",
+            "2024-03-01T09:00",
+            5,
+            "ozone",
+        );
+        let fetcher = FileFetcher::<ForecastFetcher>::new(&dir, berlin()).unwrap();
+
+        let forecast = fetcher.fetch_recent().await.unwrap();
+        let leads: Vec<_> = forecast.iter().map(|f| f.lead_hours).collect();
+        assert_eq!(leads, [1, 2, 3]);
+        assert!(forecast
+            .iter()
+            .all(|f| f.issue_time == hour("2024-03-01T10:00")));
+        assert!(matches!(
+            fetcher.fetch_recent().await,
+            Err(Error::RecordingExhausted { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_what_a_live_run_recorded() {
+        let dir = temp_dir("replay_live");
+        let mut http = serve(response("2024-03-01T00:00", 48, "pm10")).await;
+        http.record = Some(dir.clone());
+        let live = APIFetcher {
+            http,
+            location: berlin(),
+        };
+        let fetched = live
+            .fetch_historical("2024-03-01", "2024-03-02")
+            .await
+            .unwrap();
+
+        let replayed = FileFetcher::<APIFetcher>::new(&dir, berlin())
+            .unwrap()
+            .fetch_historical("2024-03-01", "2024-03-02")
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&replayed).unwrap(),
+            serde_json::to_string(&fetched).unwrap()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{DateTime, Duration as TimeDuration, DurationRound, Utc};
+use std::str::FromStr;
+
+use crate::config::ScheduleConfig;
+use crate::error::{Error, Result};
+
+pub enum Schedule {
+    Hourly { offset: TimeDuration },
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig) -> Result<Self> {
+        if let Some(expression) = &config.cron {
+            let schedule = cron::Schedule::from_str(expression).map_err(|e| {
+                Error::Config(config::ConfigError::Message(format!(
+                    "invalid cron expression '{}': {}",
+                    expression, e
+                )))
+            })?;
+            if schedule.upcoming(Utc).next().is_none() {
+                return Err(Error::Config(config::ConfigError::Message(format!(
+                    "cron expression '{}' never fires again",
+                    expression
+                ))));
+            }
+            return Ok(Schedule::Cron(Box::new(schedule)));
+        }
+
+        if config.offset_minutes >= 60 {
+            return Err(Error::Config(config::ConfigError::Message(format!(
+                "schedule offset must be below 60 minutes, got {}",
+                config.offset_minutes
+            ))));
+        }
+        Ok(Schedule::Hourly {
+            offset: TimeDuration::minutes(config.offset_minutes.into()),
+        })
+    }
+
+    /// Fails once a cron expression limited to some years runs out of ticks.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        match self {
+            Schedule::Hourly { offset } => {
+                let hour = after
+                    .duration_trunc(TimeDuration::hours(1))
+                    .expect("an hour always fits a timestamp");
+                let tick = hour + *offset;
+                if tick > after {
+                    Ok(tick)
+                } else {
+                    Ok(tick + TimeDuration::hours(1))
+                }
+            }
+            Schedule::Cron(schedule) => schedule.after(&after).next().ok_or_else(|| {
+                Error::Config(config::ConfigError::Message(format!(
+                    "cron expression '{}' has no ticks after {}",
+                    schedule, after
+                )))
+            }),
+        }
+    }
+
+    /// Ticks strictly between `from` and `to`.
+    pub fn missed_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> usize {
+        let mut missed = 0;
+        let mut after = from;
+        while let Ok(tick) = self.next_after(after) {
+            if tick >= to {
+                break;
+            }
+            missed += 1;
+            after = tick;
+        }
+        missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn hourly(offset_minutes: u32) -> Schedule {
+        Schedule::from_config(&ScheduleConfig {
+            offset_minutes,
+            cron: None,
+        })
+        .unwrap()
+    }
+
+    fn cron(expression: &str) -> Schedule {
+        Schedule::from_config(&ScheduleConfig {
+            offset_minutes: 0,
+            cron: Some(expression.to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn hourly_ticks_at_the_offset_past_each_hour() {
+        let schedule = hourly(5);
+        assert_eq!(
+            schedule.next_after(at("2024-01-01T10:00:00Z")).unwrap(),
+            at("2024-01-01T10:05:00Z")
+        );
+        // A tick is never its own successor
+        assert_eq!(
+            schedule.next_after(at("2024-01-01T10:05:00Z")).unwrap(),
+            at("2024-01-01T11:05:00Z")
+        );
+        assert_eq!(
+            schedule.next_after(at("2024-01-01T23:30:00Z")).unwrap(),
+            at("2024-01-02T00:05:00Z")
+        );
+    }
+
+    #[test]
+    fn cron_ticks_follow_the_expression() {
+        let schedule = cron("0 30 */6 * * *");
+        assert_eq!(
+            schedule.next_after(at("2024-01-01T06:30:00Z")).unwrap(),
+            at("2024-01-01T12:30:00Z")
+        );
+    }
+
+    #[test]
+    fn cron_ticks_can_run_out() {
+        let schedule = cron("0 0 0 1 1 * 2099");
+        assert!(matches!(
+            schedule.next_after(at("2099-01-01T00:00:00Z")),
+            Err(Error::Config(_))
+        ));
+        assert_eq!(
+            schedule.missed_between(at("2098-12-31T00:00:00Z"), at("2100-01-01T00:00:00Z")),
+            1
+        );
+    }
+
+    #[test]
+    fn missed_ticks_are_counted_between_wakeups() {
+        let schedule = hourly(5);
+        let woken = at("2024-01-01T10:05:00Z");
+        assert_eq!(
+            schedule.missed_between(woken, at("2024-01-01T11:05:00Z")),
+            0
+        );
+        assert_eq!(
+            schedule.missed_between(woken, at("2024-01-01T11:06:00Z")),
+            1
+        );
+        assert_eq!(
+            schedule.missed_between(woken, at("2024-01-01T14:00:00Z")),
+            3
+        );
+
+        let schedule = cron("0 0 */6 * * *");
+        assert_eq!(
+            schedule.missed_between(at("2024-01-01T00:00:00Z"), at("2024-01-02T01:00:00Z")),
+            4
+        );
+    }
+
+    #[test]
+    fn bad_configs_are_rejected() {
+        let bad = [
+            ScheduleConfig {
+                offset_minutes: 60,
+                cron: None,
+            },
+            ScheduleConfig {
+                offset_minutes: 0,
+                cron: Some("every hour".to_string()),
+            },
+            ScheduleConfig {
+                offset_minutes: 0,
+                cron: Some("0 0 0 1 1 * 2000".to_string()),
+            },
+        ];
+        for config in &bad {
+            assert!(matches!(
+                Schedule::from_config(config),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+}
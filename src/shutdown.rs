@@ -0,0 +1,69 @@
+use std::io;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+/// Checked by long-running loops between units of work.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Listens for SIGINT and SIGTERM, then exits forcefully after `deadline`.
+    pub fn listen(deadline: Duration) -> io::Result<Self> {
+        let (tx, rx) = watch::channel(false);
+
+        #[cfg(unix)]
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            #[cfg(not(unix))]
+            let _ = tokio::signal::ctrl_c().await;
+
+            info!(
+                "Shutdown requested, finishing current work (deadline {:?})",
+                deadline
+            );
+            let _ = tx.send(true);
+
+            tokio::time::sleep(deadline).await;
+            error!("Did not shut down within {:?}, exiting", deadline);
+            std::process::exit(1);
+        });
+
+        Ok(Shutdown { rx })
+    }
+
+    /// A handle that is never triggered.
+    pub fn never() -> Self {
+        let (tx, rx) = watch::channel(false);
+        // Keep the sender alive for as long as anyone is listening
+        tokio::spawn(async move { tx.closed().await });
+        Shutdown { rx }
+    }
+
+    /// A handle that has already been triggered.
+    #[cfg(test)]
+    pub fn requested() -> Self {
+        let (_, rx) = watch::channel(true);
+        Shutdown { rx }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once shutdown has been requested.
+    pub async fn triggered(&mut self) {
+        // The sender lives until the process exits, so this only errors if
+        // the listener task panicked, which is as good as a shutdown request.
+        let _ = self.rx.wait_for(|triggered| *triggered).await;
+    }
+}
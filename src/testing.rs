@@ -0,0 +1,94 @@
+//! Fixtures shared by the unit tests.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::air_models::time_format::parse_date;
+use crate::config::{ConsumerConfig, LocationConfig};
+use crate::error::{Error, Result};
+use crate::traits::data_fetcher::HourlyRecord;
+use crate::traits::data_loader::{ConflictStrategy, Persistable};
+
+/// A path unique to this test run that does not exist yet.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rust_kafka_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+pub(crate) fn berlin() -> LocationConfig {
+    LocationConfig {
+        name: "berlin".to_string(),
+        latitude: 52.52,
+        longitude: 13.41,
+        tags: Vec::new(),
+    }
+}
+
+pub(crate) fn date(value: &str) -> NaiveDate {
+    parse_date(value).unwrap()
+}
+
+/// The smallest [`HourlyRecord`] there is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Reading {
+    pub location: String,
+    pub time: DateTime<Utc>,
+    pub value: f64,
+}
+
+impl HourlyRecord for Reading {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+}
+
+pub(crate) fn reading(location: &str, time: &str, value: f64) -> Reading {
+    Reading {
+        location: location.to_string(),
+        time: time.parse().unwrap(),
+        value,
+    }
+}
+
+/// Batches from "offline" fail like a database that is down, negative
+/// readings are rejected like a constraint violation.
+#[async_trait]
+impl Persistable for Vec<Reading> {
+    async fn save_to_db(&self, _pool: &PgPool, _strategy: ConflictStrategy) -> Result<()> {
+        if self.iter().any(|r| r.location == "offline") {
+            return Err(Error::Database(sqlx::Error::PoolTimedOut));
+        }
+        if self.iter().any(|r| r.value < 0.0) {
+            return Err(Error::Database(sqlx::Error::Protocol(
+                "violates check constraint".to_string(),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A pool that never connects, for saving [`Reading`]s.
+pub(crate) fn unused_pool() -> PgPool {
+    PgPoolOptions::new()
+        .connect_lazy("postgres://localhost/unused")
+        .unwrap()
+}
+
+pub(crate) fn quick_retry() -> ConsumerConfig {
+    ConsumerConfig {
+        db_max_retries: 2,
+        db_retry_base_ms: 1,
+        db_retry_max_ms: 1,
+    }
+}
@@ -1,15 +1,38 @@
-use crate::air_models::AirQualityHourly;
+use crate::config::LocationConfig;
+use crate::error::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// One hour of readings for one location, as sent over the bus.
+pub trait HourlyRecord: Serialize + DeserializeOwned + Send + Sync {
+    fn location(&self) -> &str;
+
+    /// The hour this reading is for
+    fn time(&self) -> DateTime<Utc>;
+}
+
+/// A record built from an API response one variable column at a time.
+pub trait FromColumns: HourlyRecord {
+    fn empty(location: &str, time: DateTime<Utc>) -> Self;
+
+    fn set(&mut self, variable: &str, value: Option<f64>);
+}
 
 #[async_trait]
 pub trait DataFetcher {
-    async fn fetch_historical(
-        &self,
-        start_date: &str,
-        end_date: &str,
-    ) -> Result<Vec<AirQualityHourly>, Box<dyn std::error::Error + Send + Sync>>;
-
-    async fn fetch_recent(
-        &self,
-    ) -> Result<Vec<AirQualityHourly>, Box<dyn std::error::Error + Send + Sync>>;
+    type Record: HourlyRecord;
+
+    fn location(&self) -> &LocationConfig;
+
+    /// Replays fetch back to back instead of on the schedule.
+    fn is_replay(&self) -> bool {
+        false
+    }
+
+    async fn fetch_historical(&self, start_date: &str, end_date: &str)
+        -> Result<Vec<Self::Record>>;
+
+    async fn fetch_recent(&self) -> Result<Vec<Self::Record>>;
 }
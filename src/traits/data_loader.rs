@@ -1,7 +1,146 @@
 use async_trait::async_trait;
+use serde::Deserialize;
 use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::traits::data_fetcher::HourlyRecord;
+
+/// What to do when a row with the same key already exists.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Leave the stored row untouched and drop the incoming one.
+    #[default]
+    Skip,
+    /// Replace the stored values with the incoming ones.
+    Overwrite,
+    /// Keep the stored values; later writes only fill in empty columns.
+    KeepFirst,
+}
+
+impl ConflictStrategy {
+    /// `json_columns` hold JSONB objects, which `KeepFirst` merges key by key.
+    pub fn on_conflict(
+        &self,
+        table: &str,
+        key: &[&str],
+        columns: &[&str],
+        json_columns: &[&str],
+    ) -> String {
+        let key = key.join(", ");
+        match self {
+            ConflictStrategy::Skip => format!("ON CONFLICT ({}) DO NOTHING", key),
+            ConflictStrategy::Overwrite => {
+                let updates: Vec<String> = columns
+                    .iter()
+                    .chain(json_columns)
+                    .map(|c| format!("{c} = EXCLUDED.{c}"))
+                    .collect();
+                format!(
+                    "ON CONFLICT ({}) DO UPDATE SET {}, insert_time = CURRENT_TIMESTAMP",
+                    key,
+                    updates.join(", ")
+                )
+            }
+            ConflictStrategy::KeepFirst => {
+                let updates: Vec<String> = columns
+                    .iter()
+                    .map(|c| format!("{c} = COALESCE({table}.{c}, EXCLUDED.{c})"))
+                    .chain(
+                        json_columns
+                            .iter()
+                            .map(|c| format!("{c} = EXCLUDED.{c} || {table}.{c}")),
+                    )
+                    .collect();
+                format!("ON CONFLICT ({}) DO UPDATE SET {}", key, updates.join(", "))
+            }
+        }
+    }
+}
+
+/// Splits a batch into rounds that each hold a location and hour once, since
+/// Postgres rejects a statement touching one row twice.
+pub fn rounds<R: HourlyRecord>(batch: &[R]) -> Vec<Vec<&R>> {
+    let mut seen = HashMap::new();
+    let mut rounds: Vec<Vec<&R>> = Vec::new();
+    for record in batch {
+        let round = seen.entry((record.location(), record.time())).or_insert(0);
+        if *round == rounds.len() {
+            rounds.push(Vec::new());
+        }
+        rounds[*round].push(record);
+        *round += 1;
+    }
+    rounds
+}
 
 #[async_trait]
 pub trait Persistable {
-    async fn save_to_db(&self, pool: &PgPool) -> Result<(), sqlx::Error>;
+    async fn save_to_db(&self, pool: &PgPool, strategy: ConflictStrategy) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::reading;
+
+    #[test]
+    fn skip_does_nothing_on_conflict() {
+        assert_eq!(
+            ConflictStrategy::Skip.on_conflict(
+                "air_quality",
+                &["location", "_time"],
+                &["pm10"],
+                &["extra"],
+            ),
+            "ON CONFLICT (location, _time) DO NOTHING"
+        );
+    }
+
+    #[test]
+    fn overwrite_takes_the_incoming_values() {
+        assert_eq!(
+            ConflictStrategy::Overwrite.on_conflict(
+                "air_quality",
+                &["location", "_time"],
+                &["pm10", "pm2_5"],
+                &["extra"],
+            ),
+            "ON CONFLICT (location, _time) DO UPDATE SET pm10 = EXCLUDED.pm10, \
+             pm2_5 = EXCLUDED.pm2_5, extra = EXCLUDED.extra, \
+             insert_time = CURRENT_TIMESTAMP"
+        );
+    }
+
+    #[test]
+    fn keep_first_only_fills_in_empty_values() {
+        assert_eq!(
+            ConflictStrategy::KeepFirst.on_conflict(
+                "air_quality",
+                &["location", "_time"],
+                &["pm10"],
+                &["extra"],
+            ),
+            "ON CONFLICT (location, _time) DO UPDATE SET \
+             pm10 = COALESCE(air_quality.pm10, EXCLUDED.pm10), \
+             extra = EXCLUDED.extra || air_quality.extra"
+        );
+    }
+
+    #[test]
+    fn repeated_hours_go_to_later_rounds() {
+        let batch = [
+            reading("Berlin", "2024-01-01T00:00:00Z", 1.0),
+            reading("Berlin", "2024-01-01T01:00:00Z", 2.0),
+            reading("Berlin", "2024-01-01T00:00:00Z", 3.0),
+            reading("Paris", "2024-01-01T00:00:00Z", 4.0),
+            reading("Berlin", "2024-01-01T00:00:00Z", 5.0),
+        ];
+        let values: Vec<Vec<f64>> = rounds(&batch)
+            .iter()
+            .map(|round| round.iter().map(|reading| reading.value).collect())
+            .collect();
+        assert_eq!(values, [vec![1.0, 2.0, 4.0], vec![3.0], vec![5.0]]);
+    }
 }
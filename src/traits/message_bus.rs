@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+
+use crate::error::Result;
+
+#[derive(Debug, Clone)]
+pub struct BusMessage {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub payload: Option<Vec<u8>>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl BusMessage {
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[async_trait]
+pub trait MessagePublisher: Sync {
+    /// Sends one message and waits until the backend has accepted it.
+    async fn publish(
+        &self,
+        topic: &str,
+        key: Option<&str>,
+        payload: &[u8],
+        headers: &[(&str, &str)],
+    ) -> Result<()>;
+
+    /// Waits for anything still buffered to be delivered.
+    async fn flush(&self) -> Result<()>;
+}
+
+#[async_trait]
+pub trait MessageSubscriber: Send {
+    /// The next message, or `None` once the source has nothing more to give.
+    async fn next(&mut self) -> Option<Result<BusMessage>>;
+
+    /// Marks `msg`, and everything before it in its partition, as processed.
+    async fn commit(&mut self, msg: &BusMessage) -> Result<()>;
+
+    /// Makes sure all commits have been stored before the subscriber goes away.
+    async fn close(&mut self) -> Result<()>;
+}
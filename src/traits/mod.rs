@@ -1,2 +1,3 @@
 pub mod data_fetcher;
 pub mod data_loader;
+pub mod message_bus;
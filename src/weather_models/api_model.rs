@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::air_models::time_format::parse_date;
+use crate::api_client::{latest_until, ApiClient, TIMEZONE};
+use crate::config::LocationConfig;
+use crate::error::Result;
+use crate::replay::{historical_name, recent_name, Recorded};
+use crate::traits::data_fetcher::DataFetcher;
+use crate::weather_models::weather_model::{RawWeather, WeatherHourly, WEATHER_VARIABLES};
+
+/// Days the reanalysis behind the archive lags behind.
+const ARCHIVE_DELAY_DAYS: i64 = 5;
+
+const ARCHIVE_PREFIX: &str = "weather_archive_";
+const FORECAST_PREFIX: &str = "weather_forecast_";
+
+pub struct WeatherFetcher {
+    pub http: ApiClient,
+    pub location: LocationConfig,
+}
+
+impl WeatherFetcher {
+    fn url(&self, endpoint: &str, range: &str) -> String {
+        format!(
+            "{}?latitude={}&longitude={}&{}&timezone={}&hourly={}",
+            endpoint,
+            self.location.latitude,
+            self.location.longitude,
+            range,
+            TIMEZONE,
+            WEATHER_VARIABLES.join(",")
+        )
+    }
+
+    async fn fetch_days(
+        &self,
+        prefix: &str,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<WeatherHourly>> {
+        let (start, end) = (start.format("%Y-%m-%d"), end.format("%Y-%m-%d"));
+        let url = self.url(endpoint, &format!("start_date={}&end_date={}", start, end));
+        let name = historical_name(prefix, start, end);
+        let raw_data = self.http.get(&url, &self.location.name, &name).await?;
+        Self::historical(raw_data, &self.location.name)
+    }
+}
+
+impl Recorded for WeatherFetcher {
+    type Raw = RawWeather;
+    type Record = WeatherHourly;
+
+    const RECENT_PREFIX: &'static str = "weather_recent_";
+    const HISTORICAL_PREFIXES: &'static [&'static str] = &[ARCHIVE_PREFIX, FORECAST_PREFIX];
+
+    fn recent(
+        raw: RawWeather,
+        location: &str,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<Vec<WeatherHourly>> {
+        Ok(latest_until(
+            raw.hourly.into_records(raw.utc_offset_seconds, location)?,
+            fetched_at,
+        ))
+    }
+
+    fn historical(raw: RawWeather, location: &str) -> Result<Vec<WeatherHourly>> {
+        raw.hourly.into_records(raw.utc_offset_seconds, location)
+    }
+}
+
+#[async_trait]
+impl DataFetcher for WeatherFetcher {
+    type Record = WeatherHourly;
+
+    fn location(&self) -> &LocationConfig {
+        &self.location
+    }
+
+    async fn fetch_recent(&self) -> Result<Vec<WeatherHourly>> {
+        let url = self.url(
+            &self.http.api.forecast_url(),
+            "past_hours=1&forecast_hours=1",
+        );
+        let now = Utc::now();
+
+        let name = recent_name(Self::RECENT_PREFIX, now);
+        let raw_data = self.http.get(&url, &self.location.name, &name).await?;
+        Self::recent(raw_data, &self.location.name, now)
+    }
+
+    /// Days the archive lacks come from the forecast API.
+    async fn fetch_historical(
+        &self,
+        start_date: &str,
+        end_date: &str,
+    ) -> Result<Vec<WeatherHourly>> {
+        let start = parse_date(start_date)?;
+        let end = parse_date(end_date)?;
+        let archived_until = Utc::now().date_naive() - Duration::days(ARCHIVE_DELAY_DAYS);
+
+        let mut hourly = Vec::new();
+        if start <= archived_until {
+            hourly.extend(
+                self.fetch_days(
+                    ARCHIVE_PREFIX,
+                    &self.http.api.archive_url(),
+                    start,
+                    end.min(archived_until),
+                )
+                .await?,
+            );
+        }
+        if end > archived_until {
+            let recent_start = start.max(archived_until + Duration::days(1));
+            hourly.extend(
+                self.fetch_days(
+                    FORECAST_PREFIX,
+                    &self.http.api.forecast_url(),
+                    recent_start,
+                    end,
+                )
+                .await?,
+            );
+        }
+        Ok(hourly)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod api_model;
+pub mod weather_model;
+
+pub use api_model::WeatherFetcher;
@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::fmt;
+use tracing::info;
+
+use crate::air_models::air_model::RawHourlyData;
+use crate::air_models::time_format;
+use crate::error::Result;
+use crate::traits::data_fetcher::{FromColumns, HourlyRecord};
+use crate::traits::data_loader::{rounds, ConflictStrategy, Persistable};
+
+pub const WEATHER_VARIABLES: [&str; 6] = [
+    "temperature_2m",
+    "relative_humidity_2m",
+    "wind_speed_10m",
+    "wind_direction_10m",
+    "precipitation",
+    "boundary_layer_height",
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawWeather {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: f64,
+    pub generationtime_ms: f64,
+    pub utc_offset_seconds: i32,
+    pub timezone: String,
+    pub timezone_abbreviation: String,
+    pub hourly: RawHourlyData,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WeatherHourly {
+    pub location: String,
+    /// The hour this reading is for
+    #[serde(with = "time_format::wire")]
+    pub time: DateTime<Utc>,
+    /// Air temperature 2 m above ground, in °C
+    pub temperature_2m: Option<f64>,
+    /// Relative humidity 2 m above ground, in %
+    pub relative_humidity_2m: Option<f64>,
+    /// Wind speed 10 m above ground, in km/h
+    pub wind_speed_10m: Option<f64>,
+    /// Direction the wind comes from 10 m above ground, in degrees
+    pub wind_direction_10m: Option<f64>,
+    /// Rain, showers and snow over the preceding hour, in mm
+    pub precipitation: Option<f64>,
+    /// Height of the planetary boundary layer, in m
+    pub boundary_layer_height: Option<f64>,
+}
+
+impl FromColumns for WeatherHourly {
+    fn empty(location: &str, time: DateTime<Utc>) -> Self {
+        WeatherHourly {
+            location: location.to_string(),
+            time,
+            temperature_2m: None,
+            relative_humidity_2m: None,
+            wind_speed_10m: None,
+            wind_direction_10m: None,
+            precipitation: None,
+            boundary_layer_height: None,
+        }
+    }
+
+    fn set(&mut self, variable: &str, value: Option<f64>) {
+        let field = match variable {
+            "temperature_2m" => &mut self.temperature_2m,
+            "relative_humidity_2m" => &mut self.relative_humidity_2m,
+            "wind_speed_10m" => &mut self.wind_speed_10m,
+            "wind_direction_10m" => &mut self.wind_direction_10m,
+            "precipitation" => &mut self.precipitation,
+            "boundary_layer_height" => &mut self.boundary_layer_height,
+            _ => return,
+        };
+        *field = value;
+    }
+}
+
+impl HourlyRecord for WeatherHourly {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+}
+
+#[async_trait]
+impl Persistable for Vec<WeatherHourly> {
+    async fn save_to_db(&self, pool: &PgPool, strategy: ConflictStrategy) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let query = format!(
+            r#"
+        INSERT INTO weather (
+            location, _time, {}
+        )
+        SELECT * FROM UNNEST(
+            $1::text[],
+            $2::timestamptz[],
+            $3::float8[],
+            $4::float8[],
+            $5::float8[],
+            $6::float8[],
+            $7::float8[],
+            $8::float8[]
+        )
+        {}
+    "#,
+            WEATHER_VARIABLES.join(", "),
+            strategy.on_conflict("weather", &["location", "_time"], &WEATHER_VARIABLES, &[])
+        );
+
+        let mut tx = pool.begin().await?;
+        for rows in rounds(self) {
+            let column = |value: fn(&WeatherHourly) -> Option<f64>| -> Vec<Option<f64>> {
+                rows.iter().map(|r| value(r)).collect()
+            };
+            let locations: Vec<&str> = rows.iter().map(|r| r.location.as_str()).collect();
+            let times: Vec<DateTime<Utc>> = rows.iter().map(|r| r.time).collect();
+
+            sqlx::query(&query)
+                .bind(&locations)
+                .bind(&times)
+                .bind(column(|r| r.temperature_2m))
+                .bind(column(|r| r.relative_humidity_2m))
+                .bind(column(|r| r.wind_speed_10m))
+                .bind(column(|r| r.wind_direction_10m))
+                .bind(column(|r| r.precipitation))
+                .bind(column(|r| r.boundary_layer_height))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        info!(target: "consumer", "Weather ingested");
+        Ok(())
+    }
+}
+
+impl fmt::Display for WeatherHourly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Weather at {} ({})", self.time, self.location)?;
+        writeln!(f, "-----------------------------------------")?;
+        writeln!(f, "Temperature:          {:?}", self.temperature_2m)?;
+        writeln!(f, "Relative Humidity:    {:?}", self.relative_humidity_2m)?;
+        writeln!(f, "Wind Speed:           {:?}", self.wind_speed_10m)?;
+        writeln!(f, "Wind Direction:       {:?}", self.wind_direction_10m)?;
+        writeln!(f, "Precipitation:        {:?}", self.precipitation)?;
+        writeln!(f, "Boundary Layer Height:{:?}", self.boundary_layer_height)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn local_times_are_shifted_to_utc() {
+        let raw: RawWeather = serde_json::from_value(json!({
+            "latitude": 40.71,
+            "longitude": -74.01,
+            "elevation": 10.0,
+            "generationtime_ms": 0.1,
+            // Eastern Standard Time, five hours behind UTC
+            "utc_offset_seconds": -18000,
+            "timezone": "America/New_York",
+            "timezone_abbreviation": "EST",
+            "hourly": {
+                "time": ["2024-01-01T23:00", "2024-01-02T00:00"],
+                "temperature_2m": [-1.5, null],
+                "precipitation": [0.2, 0.0],
+            },
+        }))
+        .unwrap();
+
+        let hourly: Vec<WeatherHourly> = raw
+            .hourly
+            .into_records(raw.utc_offset_seconds, "new-york")
+            .unwrap();
+        let times: Vec<DateTime<Utc>> = hourly.iter().map(|r| r.time).collect();
+        assert_eq!(
+            times,
+            [
+                "2024-01-02T04:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+                "2024-01-02T05:00:00Z".parse().unwrap(),
+            ]
+        );
+        assert!(hourly.iter().all(|r| r.location == "new-york"));
+        assert_eq!(hourly[0].temperature_2m, Some(-1.5));
+        assert_eq!(hourly[1].temperature_2m, None);
+        assert_eq!(hourly[1].precipitation, Some(0.0));
+    }
+}